@@ -0,0 +1,233 @@
+// rustymode: Fork of bombuscv, originally an OpenCV-based motion detection/recording software built for research on bumblebees.
+// Originally developed as bombuscv by Marco Radocchia (C) 2022
+// Modified and renamed to rustymode by Dmitry Sobolev (C) 2025
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use crate::{error::ErrorKind, Detection, Messenger};
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::{path::PathBuf, thread};
+
+/// Alert severity.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    /// Return the lowercase severity name.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Info => "info",
+            Self::Warning => "warning",
+            Self::Critical => "critical",
+        }
+    }
+}
+
+/// File attached to an alert.
+///
+/// # Fields
+/// * name: file name
+/// * content_type: MIME type
+/// * data: file content
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub name: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+impl Attachment {
+    /// Create a JPEG image attachment.
+    pub fn jpeg(name: &str, data: Vec<u8>) -> Self {
+        Self {
+            name: name.to_string(),
+            content_type: String::from("image/jpeg"),
+            data,
+        }
+    }
+
+    /// Return whether the attachment is an image.
+    pub fn is_image(&self) -> bool {
+        self.content_type.starts_with("image/")
+    }
+}
+
+/// Backend-neutral alert: each messenger decides how to render it.
+///
+/// # Fields
+/// * title: short alert title
+/// * body: alert details (may be empty)
+/// * severity: alert severity
+/// * timestamp: date&time of the alerted condition
+/// * camera: camera name
+/// * attachments: attached files (e.g. the motion snapshot)
+/// * event_id: motion event id in the event database
+/// * detection: motion detection result, for motion alerts
+/// * clip: recorded clip of the motion event, once finalized
+#[derive(Debug, Clone)]
+pub struct Alert {
+    pub title: String,
+    pub body: String,
+    pub severity: Severity,
+    pub timestamp: DateTime<Local>,
+    pub camera: String,
+    pub attachments: Vec<Attachment>,
+    pub event_id: Option<i64>,
+    pub detection: Option<Detection>,
+    pub clip: Option<PathBuf>,
+}
+
+impl Alert {
+    /// Create an alert without body nor attachments.
+    pub fn new(title: &str, severity: Severity, timestamp: DateTime<Local>, camera: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            body: String::new(),
+            severity,
+            timestamp,
+            camera: camera.to_string(),
+            attachments: Vec::new(),
+            event_id: None,
+            detection: None,
+            clip: None,
+        }
+    }
+
+    /// Set the alert body.
+    pub fn with_body(mut self, body: &str) -> Self {
+        self.body = body.to_string();
+        self
+    }
+
+    /// Set the motion event the alert refers to.
+    pub fn with_event_id(mut self, event_id: Option<i64>) -> Self {
+        self.event_id = event_id;
+        self
+    }
+
+    /// Set the motion detection the alert refers to.
+    pub fn with_detection(mut self, detection: Detection) -> Self {
+        self.detection = Some(detection);
+        self
+    }
+
+    /// Set the recorded clip of the motion event.
+    pub fn with_clip(mut self, clip: PathBuf) -> Self {
+        self.clip = Some(clip);
+        self
+    }
+
+    /// Attach a file to the alert.
+    pub fn with_attachment(mut self, attachment: Attachment) -> Self {
+        self.attachments.push(attachment);
+        self
+    }
+
+    /// Return the first image attachment, if any.
+    pub fn snapshot(&self) -> Option<&Attachment> {
+        self.attachments.iter().find(|attachment| attachment.is_image())
+    }
+
+    /// Render the alert as plain text, for backends without rich formatting.
+    pub fn text(&self) -> String {
+        let mut text = format!(
+            "{} [{}] {}",
+            self.timestamp.format("%Y-%m-%d_%H-%M-%S"),
+            self.camera,
+            self.title
+        );
+        if !self.body.is_empty() {
+            text.push_str(": ");
+            text.push_str(&self.body);
+        }
+        text
+    }
+}
+
+/// Result of an alert delivery attempt.
+///
+/// # Fields
+/// * backend: messenger name
+/// * result: delivery result
+#[derive(Debug)]
+pub struct Delivery {
+    pub backend: String,
+    pub result: Result<(), ErrorKind>,
+}
+
+/// Fan-out alert dispatcher: delivers each alert to every messenger concurrently, so a slow
+/// backend doesn't delay the others.
+///
+/// # Fields
+/// * messengers: alert backends
+#[derive(Default)]
+pub struct Dispatcher {
+    messengers: Vec<Box<dyn Messenger + Send>>,
+}
+
+impl Dispatcher {
+    /// Create a dispatcher without messengers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a messenger.
+    pub fn push(&mut self, messenger: Box<dyn Messenger + Send>) {
+        self.messengers.push(messenger);
+    }
+
+    /// Return whether no messenger is configured.
+    pub fn is_empty(&self) -> bool {
+        self.messengers.is_empty()
+    }
+
+    /// Return the messenger names.
+    pub fn names(&self) -> Vec<&str> {
+        self.messengers.iter().map(|messenger| messenger.name()).collect()
+    }
+
+    /// Deliver the alert to every messenger, returning the deliveries in messenger order.
+    pub fn dispatch(&mut self, alert: &Alert) -> Vec<Delivery> {
+        let names: Vec<String> = self.names().into_iter().map(String::from).collect();
+        thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .messengers
+                .iter_mut()
+                .map(|messenger| {
+                    scope.spawn(move || Delivery {
+                        backend: messenger.name().to_string(),
+                        result: messenger.send(alert),
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .zip(names)
+                .map(|(handle, backend)| {
+                    handle.join().unwrap_or_else(|_| Delivery {
+                        backend,
+                        result: Err(ErrorKind::AlertErr("messenger panicked".to_string())),
+                    })
+                })
+                .collect()
+        })
+    }
+}
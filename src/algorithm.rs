@@ -0,0 +1,338 @@
+// rustymode: Fork of bombuscv, originally an OpenCV-based motion detection/recording software built for research on bumblebees.
+// Originally developed as bombuscv by Marco Radocchia (C) 2022
+// Modified and renamed to rustymode by Dmitry Sobolev (C) 2025
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use crate::{config::DetectorSettings, error::ErrorKind};
+use opencv::{
+    core::{absdiff, convert_scale_abs, Ptr, Scalar, CV_32FC3, CV_8UC1},
+    imgproc::{accumulate_weighted, cvt_color, COLOR_BGR2GRAY},
+    prelude::{Mat, MatTraitConst},
+    video::{
+        create_background_subtractor_knn, create_background_subtractor_mog2,
+        BackgroundSubtractorKNN, BackgroundSubtractorKNNTrait, BackgroundSubtractorMOG2,
+        BackgroundSubtractorMOG2Trait, BackgroundSubtractorTrait,
+    },
+};
+use serde::Deserialize;
+use std::str::FromStr;
+
+/// Motion detection algorithms.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Algorithm {
+    /// Difference against the previous frame.
+    Absdiff,
+    /// Difference against an exponentially weighted running average background.
+    RunningAverage,
+    /// OpenCV Gaussian mixture based background subtractor.
+    Mog2,
+    /// OpenCV K-nearest neighbours based background subtractor.
+    Knn,
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(algorithm: &str) -> Result<Self, Self::Err> {
+        match algorithm {
+            "absdiff" => Ok(Self::Absdiff),
+            "running_average" => Ok(Self::RunningAverage),
+            "mog2" => Ok(Self::Mog2),
+            "knn" => Ok(Self::Knn),
+            _ => Err(format!(
+                "unknown algorithm '{algorithm}' (valid: absdiff, running_average, mog2, knn)"
+            )),
+        }
+    }
+}
+
+/// Motion detection (background subtraction) algorithm.
+///
+/// Receives downscaled BGR frames and produces a single channel foreground image, where
+/// brighter pixels mean bigger change: the motion detector thresholds it to find motion.
+pub trait MotionAlgorithm {
+    /// Compute the foreground image of `frame`, updating the background model.
+    fn apply(&mut self, frame: &Mat, foreground: &mut Mat) -> Result<(), ErrorKind>;
+    /// Discard the background model, rebuilding it from the next frames.
+    fn reset(&mut self);
+}
+
+/// Create an instance of the motion detection algorithm selected in `settings`.
+///
+/// # Parameters
+/// * settings: motion detection tuning parameters
+pub fn new(settings: &DetectorSettings) -> Result<Box<dyn MotionAlgorithm + Send>, ErrorKind> {
+    Ok(match settings.algorithm {
+        Algorithm::Absdiff => Box::new(AbsDiff::new()),
+        Algorithm::RunningAverage => Box::new(RunningAverage::new(settings.running_average_alpha)),
+        Algorithm::Mog2 => Box::new(Mog2::new(
+            settings.history,
+            settings.mog2_var_threshold,
+            settings.detect_shadows,
+            settings.learning_rate,
+        )?),
+        Algorithm::Knn => Box::new(Knn::new(
+            settings.history,
+            settings.knn_dist2_threshold,
+            settings.detect_shadows,
+            settings.learning_rate,
+        )?),
+    })
+}
+
+/// Convert BGR frame difference to grayscale foreground image.
+fn to_gray(diff: &Mat, foreground: &mut Mat) -> Result<(), ErrorKind> {
+    cvt_color(
+        diff,
+        foreground,
+        COLOR_BGR2GRAY, // Color space conversion code (see #ColorConversionCodes).
+        0, // Number of channels in the destination image; if the parameter is 0, the number of the channels is derived automatically from src and code.
+    )
+    .map_err(|e| ErrorKind::MotionAlgorithmErr(e.to_string()))
+}
+
+/// Frame-to-previous-frame absolute difference.
+///
+/// # Fields
+/// * prev_frame: previous frame to make comparisons
+#[derive(Default)]
+pub struct AbsDiff {
+    prev_frame: Option<Mat>,
+}
+
+impl AbsDiff {
+    /// Create an instance of the absolute difference algorithm.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl MotionAlgorithm for AbsDiff {
+    fn apply(&mut self, frame: &Mat, foreground: &mut Mat) -> Result<(), ErrorKind> {
+        // Update the previous frame.
+        let Some(prev_frame) = self.prev_frame.replace(frame.clone()) else {
+            // First frame: nothing to compare against yet, so no change at all.
+            *foreground = Mat::new_rows_cols_with_default(
+                frame.rows(),
+                frame.cols(),
+                CV_8UC1,
+                Scalar::all(0.),
+            )
+            .map_err(|e| ErrorKind::MotionAlgorithmErr(e.to_string()))?;
+            return Ok(());
+        };
+
+        // Calculate absolute difference of pixel values.
+        let mut diff = Mat::default();
+        absdiff(&prev_frame, frame, &mut diff)
+            .map_err(|e| ErrorKind::MotionAlgorithmErr(e.to_string()))?;
+
+        // HELP: this are for graphical example
+        // highgui::imshow("bombuscv", &diff).unwrap();
+        // highgui::wait_key(1).unwrap();
+
+        to_gray(&diff, foreground)
+    }
+
+    fn reset(&mut self) {
+        self.prev_frame = None;
+    }
+}
+
+/// Exponentially weighted running average background.
+///
+/// # Fields
+/// * alpha: weight of the newest frame in the background average
+/// * background: floating point background model
+pub struct RunningAverage {
+    alpha: f64,
+    background: Option<Mat>,
+}
+
+impl RunningAverage {
+    /// Create an instance of the running average algorithm.
+    pub fn new(alpha: f64) -> Self {
+        Self {
+            alpha,
+            background: None,
+        }
+    }
+}
+
+impl MotionAlgorithm for RunningAverage {
+    fn apply(&mut self, frame: &Mat, foreground: &mut Mat) -> Result<(), ErrorKind> {
+        let map_err = |e: opencv::Error| ErrorKind::MotionAlgorithmErr(e.to_string());
+
+        // Seed the background model with the first frame.
+        if self.background.is_none() {
+            let mut background = Mat::default();
+            frame
+                .convert_to(&mut background, CV_32FC3, 1., 0.)
+                .map_err(map_err)?;
+            self.background = Some(background);
+        }
+        let background = self.background.as_mut().expect("background model not seeded");
+
+        // Compare the frame against the current background.
+        let mut background_u8 = Mat::default();
+        let mut diff = Mat::default();
+        convert_scale_abs(&*background, &mut background_u8, 1., 0.).map_err(map_err)?;
+        absdiff(&background_u8, frame, &mut diff).map_err(map_err)?;
+
+        // Blend the frame into the background.
+        accumulate_weighted(frame, background, self.alpha, &Mat::default()).map_err(map_err)?;
+
+        to_gray(&diff, foreground)
+    }
+
+    fn reset(&mut self) {
+        self.background = None;
+    }
+}
+
+/// OpenCV BackgroundSubtractorMOG2.
+///
+/// # Fields
+/// * subtractor: OpenCV background subtractor instance
+/// * history: length of the history
+/// * var_threshold: threshold on the squared Mahalanobis distance
+/// * detect_shadows: detect shadows and exclude them from the foreground
+/// * learning_rate: background model learning rate (negative for automatic)
+pub struct Mog2 {
+    subtractor: Ptr<BackgroundSubtractorMOG2>,
+    history: i32,
+    var_threshold: f64,
+    detect_shadows: bool,
+    learning_rate: f64,
+}
+
+impl Mog2 {
+    /// Create an instance of the MOG2 background subtractor.
+    pub fn new(
+        history: i32,
+        var_threshold: f64,
+        detect_shadows: bool,
+        learning_rate: f64,
+    ) -> Result<Self, ErrorKind> {
+        Ok(Self {
+            subtractor: Self::subtractor(history, var_threshold, detect_shadows)?,
+            history,
+            var_threshold,
+            detect_shadows,
+            learning_rate,
+        })
+    }
+
+    /// Construct the OpenCV background subtractor.
+    fn subtractor(
+        history: i32,
+        var_threshold: f64,
+        detect_shadows: bool,
+    ) -> Result<Ptr<BackgroundSubtractorMOG2>, ErrorKind> {
+        let mut subtractor =
+            create_background_subtractor_mog2(history, var_threshold, detect_shadows)
+                .map_err(|e| ErrorKind::MotionAlgorithmErr(e.to_string()))?;
+        // Mark shadows as background so they are never reported as motion.
+        subtractor
+            .set_shadow_value(0)
+            .map_err(|e| ErrorKind::MotionAlgorithmErr(e.to_string()))?;
+
+        Ok(subtractor)
+    }
+}
+
+impl MotionAlgorithm for Mog2 {
+    fn apply(&mut self, frame: &Mat, foreground: &mut Mat) -> Result<(), ErrorKind> {
+        self.subtractor
+            .apply(frame, foreground, self.learning_rate)
+            .map_err(|e| ErrorKind::MotionAlgorithmErr(e.to_string()))
+    }
+
+    fn reset(&mut self) {
+        if let Ok(subtractor) =
+            Self::subtractor(self.history, self.var_threshold, self.detect_shadows)
+        {
+            self.subtractor = subtractor;
+        }
+    }
+}
+
+/// OpenCV BackgroundSubtractorKNN.
+///
+/// # Fields
+/// * subtractor: OpenCV background subtractor instance
+/// * history: length of the history
+/// * dist2_threshold: threshold on the squared distance between pixel and sample
+/// * detect_shadows: detect shadows and exclude them from the foreground
+/// * learning_rate: background model learning rate (negative for automatic)
+pub struct Knn {
+    subtractor: Ptr<BackgroundSubtractorKNN>,
+    history: i32,
+    dist2_threshold: f64,
+    detect_shadows: bool,
+    learning_rate: f64,
+}
+
+impl Knn {
+    /// Create an instance of the KNN background subtractor.
+    pub fn new(
+        history: i32,
+        dist2_threshold: f64,
+        detect_shadows: bool,
+        learning_rate: f64,
+    ) -> Result<Self, ErrorKind> {
+        Ok(Self {
+            subtractor: Self::subtractor(history, dist2_threshold, detect_shadows)?,
+            history,
+            dist2_threshold,
+            detect_shadows,
+            learning_rate,
+        })
+    }
+
+    /// Construct the OpenCV background subtractor.
+    fn subtractor(
+        history: i32,
+        dist2_threshold: f64,
+        detect_shadows: bool,
+    ) -> Result<Ptr<BackgroundSubtractorKNN>, ErrorKind> {
+        let mut subtractor =
+            create_background_subtractor_knn(history, dist2_threshold, detect_shadows)
+                .map_err(|e| ErrorKind::MotionAlgorithmErr(e.to_string()))?;
+        // Mark shadows as background so they are never reported as motion.
+        subtractor
+            .set_shadow_value(0)
+            .map_err(|e| ErrorKind::MotionAlgorithmErr(e.to_string()))?;
+
+        Ok(subtractor)
+    }
+}
+
+impl MotionAlgorithm for Knn {
+    fn apply(&mut self, frame: &Mat, foreground: &mut Mat) -> Result<(), ErrorKind> {
+        self.subtractor
+            .apply(frame, foreground, self.learning_rate)
+            .map_err(|e| ErrorKind::MotionAlgorithmErr(e.to_string()))
+    }
+
+    fn reset(&mut self) {
+        if let Ok(subtractor) =
+            Self::subtractor(self.history, self.dist2_threshold, self.detect_shadows)
+        {
+            self.subtractor = subtractor;
+        }
+    }
+}
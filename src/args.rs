@@ -15,10 +15,18 @@
 // You should have received a copy of the GNU General Public License along with
 // this program. If not, see https://www.gnu.org/licenses/.
 
-use crate::config::expand_home;
-use clap::ArgAction::{Set, SetTrue};
+use crate::{
+    algorithm::Algorithm,
+    config::{expand_home, RecordingMode},
+    Codec, Container,
+};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use clap::{
+    ArgAction::{Set, SetTrue},
+    Subcommand,
+};
 pub use clap::Parser;
-use std::{fs, path::PathBuf};
+use std::{fs, path::PathBuf, str::FromStr};
 
 /// Custom parser for `directory` field.
 /// Automatically expands ~ and creates directory if doesn't exist.
@@ -40,6 +48,48 @@ fn parse_video(video: &str) -> Result<PathBuf, String> {
     }
 }
 
+/// Custom parser for `blur_kernel` field.
+fn parse_blur_kernel(blur_kernel: &str) -> Result<u8, String> {
+    match blur_kernel.parse::<u8>() {
+        Ok(blur_kernel) if blur_kernel % 2 == 1 => Ok(blur_kernel),
+        _ => Err(String::from("blur kernel size must be a positive odd number")),
+    }
+}
+
+/// Custom parser for sizes and durations that must be at least 1.
+fn parse_positive(value: &str) -> Result<u16, String> {
+    match value.parse::<u16>() {
+        Ok(value) if value > 0 => Ok(value),
+        _ => Err(String::from("value must be at least 1")),
+    }
+}
+
+/// Custom parser for ratio fields.
+fn parse_ratio(ratio: &str) -> Result<f64, String> {
+    match ratio.parse::<f64>() {
+        Ok(ratio) if (0. ..=1.).contains(&ratio) => Ok(ratio),
+        _ => Err(String::from("ratio must be within 0 and 1")),
+    }
+}
+
+/// Custom parser for date&time fields: RFC 3339 or local `YYYY-MM-DD[ HH:MM[:SS]]`.
+fn parse_datetime(datetime: &str) -> Result<DateTime<Local>, String> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(datetime) {
+        return Ok(datetime.with_timezone(&Local));
+    }
+
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(datetime, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(datetime, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+        .and_then(|datetime| Local.from_local_datetime(&datetime).earliest())
+        .ok_or_else(|| String::from("date&time must be RFC 3339 or YYYY-MM-DD[ HH:MM[:SS]]"))
+}
+
 /// OpenCV motion detection/video-recording tool developed for research on Bumblebees.
 #[derive(Parser, Debug)]
 #[clap(
@@ -94,4 +144,141 @@ pub struct Args {
     /// Mute standard output.
     #[clap(short, long, action = SetTrue)]
     pub quiet: bool,
+
+    /// Motion detection algorithm [absdiff, running_average, mog2, knn].
+    #[clap(short, long, value_parser = Algorithm::from_str)]
+    pub algorithm: Option<Algorithm>,
+
+    /// Motion detection binary threshold (0-255).
+    #[clap(short, long, action = Set)]
+    pub threshold: Option<u8>,
+
+    /// Motion detection gaussian blur kernel size (odd).
+    #[clap(long, value_parser = parse_blur_kernel)]
+    pub blur_kernel: Option<u8>,
+
+    /// Motion detection dilate iterations.
+    #[clap(long, action = Set)]
+    pub dilate_iterations: Option<u8>,
+
+    /// Motion analysis frame width (height preserves the input aspect ratio).
+    #[clap(long, value_parser = parse_positive)]
+    pub analysis_width: Option<u16>,
+
+    /// Minimum contour area (in analysis frame pixels) to be considered motion.
+    #[clap(long, action = Set)]
+    pub min_contour_area: Option<f64>,
+
+    /// Minimum ratio (0-1) of the zones (whole frame if none) covered by qualifying contours.
+    #[clap(long, value_parser = parse_ratio)]
+    pub min_changed_ratio: Option<f64>,
+
+    /// Minimum number of qualifying contours to report motion.
+    #[clap(long, action = Set)]
+    pub min_contours: Option<u16>,
+
+    /// Changed pixels ratio (0-1) of the detection zones above which the change is treated as a
+    /// lighting event.
+    #[clap(long, value_parser = parse_ratio)]
+    pub max_changed_ratio: Option<f64>,
+
+    /// Mean luminance jump (0-255) above which the change is treated as a lighting event.
+    #[clap(long, action = Set)]
+    pub max_luminance_jump: Option<f64>,
+
+    /// Recording mode [motion, continuous].
+    #[clap(short, long, value_parser = RecordingMode::from_str)]
+    pub recording_mode: Option<RecordingMode>,
+
+    /// Output video codec [mjpg, xvid, mp4v, h264, avc1, vp80, vp90].
+    #[clap(short, long, value_parser = Codec::from_str)]
+    pub codec: Option<Codec>,
+
+    /// Output video container [mkv, mp4, avi, webm].
+    #[clap(long, value_parser = Container::from_str)]
+    pub container: Option<Container>,
+
+    /// Seconds of video recorded before the motion starts.
+    #[clap(long, action = Set)]
+    pub pre_roll: Option<u16>,
+
+    /// Seconds of video recorded after the last motion.
+    #[clap(long, action = Set)]
+    pub post_roll: Option<u16>,
+
+    /// Minutes of video per segment in continuous recording mode.
+    #[clap(long, value_parser = parse_positive)]
+    pub segment_minutes: Option<u16>,
+
+    /// Maximum total size of the recordings in MB (0 for no limit).
+    #[clap(long, action = Set)]
+    pub max_total_size: Option<u64>,
+
+    /// Maximum age of the recordings in days (0 for no limit).
+    #[clap(long, action = Set)]
+    pub max_age: Option<u16>,
+
+    /// Free disk space in MB to keep available (0 for no limit).
+    #[clap(long, action = Set)]
+    pub min_free_space: Option<u64>,
+
+    /// Write a PNG preview of the motion detection zones over a captured frame and exit.
+    #[clap(long, action = Set)]
+    pub mask_preview: Option<PathBuf>,
+
+    /// Camera name, used in events and alerts.
+    #[clap(long, action = Set)]
+    pub camera: Option<String>,
+
+    /// Motion event snapshots directory.
+    #[clap(long, action = Set)]
+    pub snapshot_dir: Option<PathBuf>,
+
+    /// Don't save motion event snapshots.
+    #[clap(long, action = SetTrue)]
+    pub no_snapshot: bool,
+
+    /// Don't send alerts.
+    #[clap(long, action = SetTrue)]
+    pub no_alerts: bool,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Subcommands.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Query the motion event database.
+    Events(EventsArgs),
+    /// Arm the alerts of the camera regardless of the schedule, until `auto`.
+    Arm,
+    /// Disarm the alerts of the camera regardless of the schedule, until `auto`.
+    Disarm,
+    /// Make the alerts of the camera follow the schedule again.
+    Auto,
+}
+
+/// Motion event database query options.
+#[derive(clap::Args, Debug)]
+pub struct EventsArgs {
+    /// Events started at or after this date&time (RFC 3339 or YYYY-MM-DD[ HH:MM[:SS]]).
+    #[clap(long, value_parser = parse_datetime)]
+    pub since: Option<DateTime<Local>>,
+
+    /// Events started at or before this date&time (RFC 3339 or YYYY-MM-DD[ HH:MM[:SS]]).
+    #[clap(long, value_parser = parse_datetime)]
+    pub until: Option<DateTime<Local>>,
+
+    /// Events of this camera only.
+    #[clap(long, action = Set)]
+    pub camera: Option<String>,
+
+    /// Events with at least this peak motion score (0-1).
+    #[clap(long, value_parser = parse_ratio)]
+    pub min_score: Option<f64>,
+
+    /// Print events as JSON.
+    #[clap(long, action = SetTrue)]
+    pub json: bool,
 }
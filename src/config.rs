@@ -15,7 +15,14 @@
 // You should have received a copy of the GNU General Public License along with
 // this program. If not, see https://www.gnu.org/licenses/.
 
-use crate::{args::Args, error::ErrorKind};
+#[cfg(feature = "slack")]
+use crate::slack::SlackSettings;
+use crate::{
+    algorithm::Algorithm, args::Args, discord::DiscordSettings, email::EmailSettings,
+    error::ErrorKind, hooks::HookSettings, matrix::MatrixSettings, mqtt::MqttSettings,
+    schedule::ScheduleSettings, telegram::TelegramSettings, webhook::WebhookSettings, zone::Zone,
+    Codec, Container,
+};
 use directories::BaseDirs;
 use serde::{de, Deserialize, Deserializer};
 use std::{
@@ -23,6 +30,7 @@ use std::{
     fmt::Debug,
     fs,
     path::{Path, PathBuf},
+    str::FromStr,
     string::String,
 };
 
@@ -114,6 +122,326 @@ fn default_streamer_encode_image() -> String {
     ".jpg".to_string()
 }
 
+/// Custom deserializer for `blur_kernel` field.
+/// Gaussian blur kernel size must be positive and odd.
+fn deserialize_blur_kernel<'de, D>(blur_kernel: D) -> Result<u8, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let blur_kernel = u8::deserialize(blur_kernel)?;
+    if blur_kernel % 2 == 0 {
+        return Err(de::Error::custom("blur kernel size must be a positive odd number"));
+    }
+
+    Ok(blur_kernel)
+}
+
+/// Custom deserializer for sizes and durations that must be at least 1.
+pub(crate) fn deserialize_positive<'de, D>(value: D) -> Result<u16, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = u16::deserialize(value)?;
+    if value == 0 {
+        return Err(de::Error::custom("value must be at least 1"));
+    }
+
+    Ok(value)
+}
+
+/// Custom deserializer for ratio fields.
+/// Ratios must be within the `[0, 1]` interval.
+fn deserialize_ratio<'de, D>(ratio: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let ratio = f64::deserialize(ratio)?;
+    if !(0. ..=1.).contains(&ratio) {
+        return Err(de::Error::custom("ratio must be within 0 and 1"));
+    }
+
+    Ok(ratio)
+}
+
+/// Motion detector settings.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DetectorSettings {
+    /// Motion detection (background subtraction) algorithm.
+    pub algorithm: Algorithm,
+
+    /// Weight of the newest frame in the `running_average` background.
+    #[serde(deserialize_with = "deserialize_ratio")]
+    pub running_average_alpha: f64,
+
+    /// Number of frames in the `mog2` and `knn` background history.
+    pub history: i32,
+
+    /// `mog2` threshold on the squared Mahalanobis distance between pixel and background model.
+    pub mog2_var_threshold: f64,
+
+    /// `knn` threshold on the squared distance between pixel and background sample.
+    pub knn_dist2_threshold: f64,
+
+    /// Detect shadows with `mog2` and `knn`, excluding them from motion.
+    pub detect_shadows: bool,
+
+    /// `mog2` and `knn` background learning rate (0-1), negative value for automatic.
+    pub learning_rate: f64,
+
+    /// Binary threshold applied to the frame difference: pixels changing less than this value
+    /// are not considered motion.
+    pub threshold: u8,
+
+    /// Gaussian blur kernel size (must be odd).
+    #[serde(deserialize_with = "deserialize_blur_kernel")]
+    pub blur_kernel: u8,
+
+    /// Gaussian blur kernel standard deviation.
+    pub blur_sigma: f64,
+
+    /// Number of times dilation is applied to the thresholded frame.
+    pub dilate_iterations: u8,
+
+    /// Width frames are downscaled to before motion analysis: height is derived from the input
+    /// frame aspect ratio.
+    #[serde(deserialize_with = "deserialize_positive")]
+    pub analysis_width: u16,
+
+    /// Minimum area (in analysis frame pixels) a contour must have to be considered motion.
+    pub min_contour_area: f64,
+
+    /// Minimum ratio of the motion detection zones (whole frame if none) covered by qualifying
+    /// contours to report motion.
+    #[serde(deserialize_with = "deserialize_ratio")]
+    pub min_changed_ratio: f64,
+
+    /// Minimum number of qualifying contours to report motion.
+    pub min_contours: u16,
+
+    /// Include/exclude zones restricting the area where motion is detected.
+    pub zones: Vec<Zone>,
+
+    /// Ratio of changed pixels within the motion detection zones (whole frame if none) above
+    /// which the change is treated as a lighting event rather than motion.
+    #[serde(deserialize_with = "deserialize_ratio")]
+    pub max_changed_ratio: f64,
+
+    /// Mean luminance (0-255) jump between consecutive frames above which the change is treated
+    /// as a lighting event rather than motion (0 to disable).
+    pub max_luminance_jump: f64,
+}
+
+/// Implement the Default trait for DetectorSettings.
+impl Default for DetectorSettings {
+    /// Default motion detector settings.
+    fn default() -> Self {
+        Self {
+            algorithm: Algorithm::Absdiff,
+            running_average_alpha: 0.05,
+            history: 500,
+            mog2_var_threshold: 16.,
+            knn_dist2_threshold: 400.,
+            detect_shadows: false,
+            learning_rate: -1.,
+            threshold: 30,
+            blur_kernel: 3,
+            blur_sigma: 21.,
+            dilate_iterations: 3,
+            analysis_width: 640,
+            min_contour_area: 0.,
+            min_changed_ratio: 0.,
+            min_contours: 1,
+            zones: Vec::new(),
+            max_changed_ratio: 1.,
+            max_luminance_jump: 0.,
+        }
+    }
+}
+
+/// Recording modes.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordingMode {
+    /// Record a clip for each motion event.
+    Motion,
+    /// Record every grabbed frame, in fixed duration segments.
+    Continuous,
+}
+
+impl FromStr for RecordingMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "motion" => Ok(Self::Motion),
+            "continuous" => Ok(Self::Continuous),
+            _ => Err(format!(
+                "unknown recording mode '{mode}' (valid: motion, continuous)"
+            )),
+        }
+    }
+}
+
+/// Recording settings.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RecordingSettings {
+    /// Recording mode.
+    pub mode: RecordingMode,
+
+    /// Output video codec.
+    pub codec: Codec,
+
+    /// Output video container.
+    pub container: Container,
+
+    /// Seconds of video recorded before the motion starts (`motion` mode).
+    pub pre_roll: u16,
+
+    /// Seconds of video recorded after the last motion (`motion` mode).
+    pub post_roll: u16,
+
+    /// Minutes of video per segment (`continuous` mode).
+    #[serde(deserialize_with = "deserialize_positive")]
+    pub segment_minutes: u16,
+
+    /// Maximum segment file size in MB, 0 for no limit (`continuous` mode).
+    pub segment_max_size: u64,
+}
+
+/// Implement the Default trait for RecordingSettings.
+impl Default for RecordingSettings {
+    /// Default recording settings.
+    fn default() -> Self {
+        Self {
+            mode: RecordingMode::Motion,
+            codec: Codec::XVID,
+            container: Container::Mkv,
+            pre_roll: 3,
+            post_roll: 5,
+            segment_minutes: 10,
+            segment_max_size: 0,
+        }
+    }
+}
+
+/// Recordings retention settings.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RetentionSettings {
+    /// Maximum total size of the recordings in MB, 0 for no limit.
+    pub max_total_size: u64,
+
+    /// Maximum age of the recordings in days, 0 for no limit.
+    pub max_age: u16,
+
+    /// Free disk space in MB to keep available, 0 for no limit.
+    pub min_free_space: u64,
+
+    /// Free disk space in MB below which no new clip is started.
+    pub critical_free_space: u64,
+}
+
+/// Implement the Default trait for RetentionSettings.
+impl Default for RetentionSettings {
+    /// Default retention settings.
+    fn default() -> Self {
+        Self {
+            max_total_size: 0,
+            max_age: 0,
+            min_free_space: 0,
+            critical_free_space: 64,
+        }
+    }
+}
+
+/// Event snapshot settings.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SnapshotSettings {
+    /// Save a JPEG snapshot of each motion event.
+    pub enabled: bool,
+
+    /// Snapshots directory (defaults to `snapshots` inside the output directory).
+    pub directory: Option<PathBuf>,
+
+    /// Draw motion bounding boxes on the snapshot.
+    pub draw_boxes: bool,
+
+    /// JPEG quality (0-100).
+    pub quality: u8,
+}
+
+/// Implement the Default trait for SnapshotSettings.
+impl Default for SnapshotSettings {
+    /// Default snapshot settings.
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            directory: None,
+            draw_boxes: true,
+            quality: 90,
+        }
+    }
+}
+
+/// Alert messengers settings: each messenger is enabled by its own table.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AlertSettings {
+    /// Send alerts (false to disable every messenger).
+    pub enabled: bool,
+
+    /// Minimum time in seconds between two motion started alerts.
+    pub cooldown: u32,
+
+    /// Interval in minutes of the motion digests replacing the per event alerts (0 to alert
+    /// each event).
+    pub digest_minutes: u32,
+
+    /// Slack messenger settings (`[alerts.slack]`).
+    #[cfg(feature = "slack")]
+    pub slack: Option<SlackSettings>,
+
+    /// Slack messenger settings, unused: built without the `slack` feature.
+    #[cfg(not(feature = "slack"))]
+    pub slack: Option<toml::Value>,
+
+    /// Generic HTTP webhook settings (`[alerts.webhook]`).
+    pub webhook: Option<WebhookSettings>,
+
+    /// Telegram messenger settings (`[alerts.telegram]`).
+    pub telegram: Option<TelegramSettings>,
+
+    /// Discord messenger settings (`[alerts.discord]`).
+    pub discord: Option<DiscordSettings>,
+
+    /// Matrix messenger settings (`[alerts.matrix]`).
+    pub matrix: Option<MatrixSettings>,
+
+    /// Email messenger settings (`[alerts.email]`).
+    pub email: Option<EmailSettings>,
+}
+
+/// Implement the Default trait for AlertSettings.
+impl Default for AlertSettings {
+    /// Default alert settings: no messenger.
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            cooldown: 5,
+            digest_minutes: 0,
+            slack: None,
+            webhook: None,
+            telegram: None,
+            discord: None,
+            matrix: None,
+            email: None,
+        }
+    }
+}
+
 /// Configuration options.
 #[derive(Deserialize, Debug)]
 pub struct Config {
@@ -165,15 +493,15 @@ pub struct Config {
     #[serde(default)]
     pub quiet: bool,
 
-    /// Slack wbehook url.
+    /// Slack wbehook url (deprecated: use `[alerts.slack]`).
     #[serde(default)]
     pub slack_url: String,
 
-    /// Slack channel.
+    /// Slack channel (deprecated: use `[alerts.slack]`).
     #[serde(default)]
     pub slack_channel: String,
 
-    /// Slack username.
+    /// Slack username (deprecated: use `[alerts.slack]`).
     #[serde(default)]
     pub slack_user: String,
 
@@ -184,6 +512,46 @@ pub struct Config {
     /// Streamer image encode type
     #[serde(default)]
     pub streamer_image_encode: String,
+
+    /// Motion detector settings.
+    #[serde(default)]
+    pub detector: DetectorSettings,
+
+    /// Recording settings.
+    #[serde(default)]
+    pub recording: RecordingSettings,
+
+    /// Recordings retention settings.
+    #[serde(default)]
+    pub retention: RetentionSettings,
+
+    /// Event snapshot settings.
+    #[serde(default)]
+    pub snapshot: SnapshotSettings,
+
+    /// Alert messengers settings.
+    #[serde(default)]
+    pub alerts: AlertSettings,
+
+    /// MQTT publisher settings (disabled if unset).
+    #[serde(default)]
+    pub mqtt: Option<MqttSettings>,
+
+    /// Shell hooks settings.
+    #[serde(default)]
+    pub hooks: HookSettings,
+
+    /// Alerting & recording schedules.
+    #[serde(default)]
+    pub schedule: ScheduleSettings,
+
+    /// Motion detection zones preview output path.
+    #[serde(skip_deserializing)]
+    pub mask_preview: Option<PathBuf>,
+
+    /// Camera name, used in events and alerts (derived from the capture source if empty).
+    #[serde(default)]
+    pub camera: String,
 }
 
 /// Implement the Default trait for Config.
@@ -207,6 +575,16 @@ impl Default for Config {
             slack_user: default_slack_user(),
             streamer_image_encode: default_streamer_encode_image(),
             streamer_listener: default_streamer_listener(),
+            detector: DetectorSettings::default(),
+            recording: RecordingSettings::default(),
+            retention: RetentionSettings::default(),
+            snapshot: SnapshotSettings::default(),
+            alerts: AlertSettings::default(),
+            mqtt: None,
+            hooks: HookSettings::default(),
+            schedule: ScheduleSettings::default(),
+            mask_preview: None,
+            camera: String::new(),
         }
     }
 }
@@ -230,12 +608,43 @@ impl Config {
             let config = fs::read_to_string(config_file).unwrap_or_default();
 
             // Deserialize toml configuration file into Config.
-            toml::from_str(&config).map_err(|e| ErrorKind::BrokenConfig(e.to_string()))
+            toml::from_str::<Self>(&config)
+                .map(Self::with_legacy_slack)
+                .map_err(|e| ErrorKind::BrokenConfig(e.to_string()))
         } else {
             Err(ErrorKind::ConfigNotFound)
         }
     }
 
+    /// Enable Slack alerts from the deprecated top-level `slack_*` options, unless the
+    /// `[alerts.slack]` table is set.
+    fn with_legacy_slack(mut self) -> Self {
+        if self.alerts.slack.is_some() || self.slack_url.is_empty() {
+            return self;
+        }
+
+        #[cfg(feature = "slack")]
+        {
+            let mut slack = SlackSettings {
+                url: self.slack_url.clone(),
+                ..Default::default()
+            };
+            if !self.slack_channel.is_empty() {
+                slack.channel = self.slack_channel.clone();
+            }
+            if !self.slack_user.is_empty() {
+                slack.user = self.slack_user.clone();
+            }
+            self.alerts.slack = Some(slack);
+        }
+        #[cfg(not(feature = "slack"))]
+        {
+            self.alerts.slack = Some(toml::Value::String(self.slack_url.clone()));
+        }
+
+        self
+    }
+
     /// Override configuration with command line arguments.
     pub fn override_with_args(mut self, args: Args) -> Self {
         if let Some(directory) = args.directory {
@@ -282,6 +691,127 @@ impl Config {
             self.overlay = true;
         }
 
+        if let Some(algorithm) = args.algorithm {
+            self.detector.algorithm = algorithm;
+        }
+
+        if let Some(threshold) = args.threshold {
+            self.detector.threshold = threshold;
+        }
+
+        if let Some(blur_kernel) = args.blur_kernel {
+            self.detector.blur_kernel = blur_kernel;
+        }
+
+        if let Some(dilate_iterations) = args.dilate_iterations {
+            self.detector.dilate_iterations = dilate_iterations;
+        }
+
+        if let Some(analysis_width) = args.analysis_width {
+            self.detector.analysis_width = analysis_width;
+        }
+
+        if let Some(min_contour_area) = args.min_contour_area {
+            self.detector.min_contour_area = min_contour_area;
+        }
+
+        if let Some(min_changed_ratio) = args.min_changed_ratio {
+            self.detector.min_changed_ratio = min_changed_ratio;
+        }
+
+        if let Some(min_contours) = args.min_contours {
+            self.detector.min_contours = min_contours;
+        }
+
+        if let Some(max_changed_ratio) = args.max_changed_ratio {
+            self.detector.max_changed_ratio = max_changed_ratio;
+        }
+
+        if let Some(max_luminance_jump) = args.max_luminance_jump {
+            self.detector.max_luminance_jump = max_luminance_jump;
+        }
+
+        if let Some(mode) = args.recording_mode {
+            self.recording.mode = mode;
+        }
+
+        if let Some(codec) = args.codec {
+            self.recording.codec = codec;
+        }
+
+        if let Some(container) = args.container {
+            self.recording.container = container;
+        }
+
+        if let Some(pre_roll) = args.pre_roll {
+            self.recording.pre_roll = pre_roll;
+        }
+
+        if let Some(post_roll) = args.post_roll {
+            self.recording.post_roll = post_roll;
+        }
+
+        if let Some(segment_minutes) = args.segment_minutes {
+            self.recording.segment_minutes = segment_minutes;
+        }
+
+        if let Some(max_total_size) = args.max_total_size {
+            self.retention.max_total_size = max_total_size;
+        }
+
+        if let Some(max_age) = args.max_age {
+            self.retention.max_age = max_age;
+        }
+
+        if let Some(min_free_space) = args.min_free_space {
+            self.retention.min_free_space = min_free_space;
+        }
+
+        if let Some(mask_preview) = args.mask_preview {
+            self.mask_preview = Some(expand_home(&mask_preview));
+        }
+
+        if let Some(camera) = args.camera {
+            self.camera = camera;
+        }
+
+        if let Some(snapshot_dir) = args.snapshot_dir {
+            self.snapshot.directory = Some(snapshot_dir);
+        }
+
+        if args.no_snapshot {
+            self.snapshot.enabled = false;
+        }
+
+        if args.no_alerts {
+            self.alerts.enabled = false;
+        }
+
         self
     }
+
+    /// Return the snapshots directory: `snapshot.directory` if set, otherwise `snapshots` inside
+    /// the output directory.
+    pub fn snapshot_directory(&self) -> PathBuf {
+        match &self.snapshot.directory {
+            Some(directory) => expand_home(directory),
+            None => self.directory.join("snapshots"),
+        }
+    }
+
+    /// Return the camera name: `camera` option if set, otherwise derived from the capture source.
+    pub fn camera_name(&self) -> String {
+        if !self.camera.is_empty() {
+            return self.camera.clone();
+        }
+
+        match &self.video {
+            Some(video) => video
+                .file_stem()
+                .map_or_else(|| video.display().to_string(), |stem| {
+                    stem.to_string_lossy().to_string()
+                }),
+            None => format!("camera{}", self.index),
+        }
+    }
 }
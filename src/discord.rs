@@ -0,0 +1,157 @@
+// rustymode: Fork of bombuscv, originally an OpenCV-based motion detection/recording software built for research on bumblebees.
+// Originally developed as bombuscv by Marco Radocchia (C) 2022
+// Modified and renamed to rustymode by Dmitry Sobolev (C) 2025
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use crate::{
+    alert::{Alert, Severity},
+    error::ErrorKind,
+    multipart::Form,
+    Messenger,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::time::Duration;
+use url::Url;
+
+/// Discord alert settings (`[alerts.discord]` table).
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DiscordSettings {
+    /// Webhook URL.
+    pub url: String,
+
+    /// Username the messages are posted as.
+    pub user: String,
+
+    /// Request timeout in seconds.
+    pub timeout: u16,
+}
+
+/// Implement the Default trait for DiscordSettings.
+impl Default for DiscordSettings {
+    /// Default Discord settings.
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            user: String::from("detector"),
+            timeout: 30,
+        }
+    }
+}
+
+/// Discord error response: only the fields used to report errors.
+#[derive(Deserialize)]
+struct ApiError {
+    #[serde(default)]
+    message: Option<String>,
+}
+
+/// Map HTTP client errors to Discord send errors, describing Discord error responses.
+fn http_err(e: ureq::Error) -> ErrorKind {
+    let msg = match e {
+        ureq::Error::Status(code, response) => response
+            .into_json::<ApiError>()
+            .ok()
+            .and_then(|error| error.message)
+            .unwrap_or_else(|| format!("HTTP status {code}")),
+        e => e.to_string(),
+    };
+    ErrorKind::UnableToSendDiscordMessage(msg)
+}
+
+/// Return the embed color of the alert severity.
+fn color(severity: Severity) -> u32 {
+    match severity {
+        Severity::Info => 0x3498db,
+        Severity::Warning => 0xf1c40f,
+        Severity::Critical => 0xe74c3c,
+    }
+}
+
+/// Discord webhook messenger: posts the alert as an embed, with the snapshot as embedded image.
+///
+/// # Fields
+/// * url: webhook URL
+/// * user: username the messages are posted as
+/// * agent: HTTP client
+pub struct DiscordMessenger {
+    pub url: String,
+    pub user: String,
+    agent: ureq::Agent,
+}
+
+/// Create the Discord messenger described by the settings.
+pub fn from_settings(settings: &DiscordSettings) -> Result<DiscordMessenger, ErrorKind> {
+    Url::parse(&settings.url)
+        .map_err(|e| ErrorKind::CreateDiscordClientErr(format!("invalid webhook URL: {e}")))?;
+
+    Ok(DiscordMessenger {
+        url: settings.url.clone(),
+        user: settings.user.clone(),
+        agent: ureq::AgentBuilder::new()
+            .timeout(Duration::from_secs(settings.timeout.into()))
+            .build(),
+    })
+}
+
+impl DiscordMessenger {
+    /// Build the webhook message of the alert, embedding the named image attachment if any.
+    fn payload(&self, alert: &Alert, image: Option<&str>) -> Value {
+        let mut embed = json!({
+            "title": alert.title,
+            "description": alert.body,
+            "timestamp": alert.timestamp.to_rfc3339(),
+            "color": color(alert.severity),
+            "footer": { "text": alert.camera },
+        });
+        if let Some(image) = image {
+            embed["image"] = json!({ "url": format!("attachment://{image}") });
+        }
+
+        json!({
+            "username": self.user,
+            "content": alert.text(),
+            "embeds": [embed],
+        })
+    }
+}
+
+impl Messenger for DiscordMessenger {
+    fn name(&self) -> &str {
+        "discord"
+    }
+
+    fn send(&mut self, alert: &Alert) -> Result<(), ErrorKind> {
+        let request = self.agent.post(&self.url);
+        match alert.snapshot() {
+            // Upload the snapshot along with the message, referenced by the embed.
+            Some(snapshot) => {
+                let payload = self.payload(alert, Some(&snapshot.name));
+                let (content_type, body) = Form::new()
+                    .text("payload_json", &payload.to_string())
+                    .file("files[0]", &snapshot.name, &snapshot.content_type, &snapshot.data)
+                    .finish();
+                request
+                    .set("Content-Type", &content_type)
+                    .send_bytes(&body)
+            }
+            None => request.send_json(self.payload(alert, None)),
+        }
+        .map_err(http_err)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,222 @@
+// rustymode: Fork of bombuscv, originally an OpenCV-based motion detection/recording software built for research on bumblebees.
+// Originally developed as bombuscv by Marco Radocchia (C) 2022
+// Modified and renamed to rustymode by Dmitry Sobolev (C) 2025
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use crate::{alert::Alert, error::ErrorKind, Messenger};
+use lettre::{
+    message::{header::ContentType, Attachment, Mailbox, MultiPart, SinglePart},
+    transport::smtp::authentication::Credentials,
+    Message, SmtpTransport, Transport,
+};
+use serde::Deserialize;
+use std::{path::Path, time::Duration};
+
+/// SMTP connection security.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Plaintext connection upgraded with STARTTLS (submission port 587).
+    Starttls,
+    /// Implicit TLS (submissions port 465).
+    Tls,
+    /// Plaintext connection, for trusted local relays only (port 25).
+    None,
+}
+
+impl SmtpTls {
+    /// Return the default port of the connection security.
+    pub fn default_port(&self) -> u16 {
+        match self {
+            Self::Starttls => 587,
+            Self::Tls => 465,
+            Self::None => 25,
+        }
+    }
+}
+
+/// Email alert settings (`[alerts.email]` table).
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct EmailSettings {
+    /// SMTP server host.
+    pub host: String,
+
+    /// SMTP server port (defaults to the connection security port).
+    pub port: Option<u16>,
+
+    /// Connection security.
+    pub tls: SmtpTls,
+
+    /// SMTP username (empty for no authentication).
+    pub username: String,
+
+    /// SMTP password.
+    pub password: String,
+
+    /// Sender address.
+    pub from: String,
+
+    /// Recipient addresses.
+    pub to: Vec<String>,
+
+    /// Prefix of the email subject.
+    pub subject_prefix: String,
+
+    /// Base URL the clips are served from, linked as `<clip_url>/<clip file name>` (empty to
+    /// link the local clip path).
+    pub clip_url: String,
+
+    /// Connection timeout in seconds.
+    pub timeout: u16,
+}
+
+/// Implement the Default trait for EmailSettings.
+impl Default for EmailSettings {
+    /// Default email settings.
+    fn default() -> Self {
+        Self {
+            host: String::new(),
+            port: None,
+            tls: SmtpTls::Starttls,
+            username: String::new(),
+            password: String::new(),
+            from: String::new(),
+            to: Vec::new(),
+            subject_prefix: String::from("[rustymode]"),
+            clip_url: String::new(),
+            timeout: 30,
+        }
+    }
+}
+
+/// Parse an email address, mapping errors to email client errors.
+fn mailbox(address: &str) -> Result<Mailbox, ErrorKind> {
+    address
+        .parse()
+        .map_err(|e| ErrorKind::CreateEmailClientErr(format!("invalid address '{address}': {e}")))
+}
+
+/// SMTP email messenger: sends each alert as multipart MIME email, with the snapshot attached
+/// and a link to the clip.
+///
+/// # Fields
+/// * transport: SMTP transport
+/// * from: sender
+/// * to: recipients
+/// * subject_prefix: prefix of the email subject
+/// * clip_url: base URL the clips are served from
+pub struct EmailMessenger {
+    transport: SmtpTransport,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    subject_prefix: String,
+    clip_url: String,
+}
+
+/// Create the email messenger described by the settings.
+pub fn from_settings(settings: &EmailSettings) -> Result<EmailMessenger, ErrorKind> {
+    if settings.host.is_empty() {
+        return Err(ErrorKind::CreateEmailClientErr("missing SMTP host".to_string()));
+    }
+    if settings.to.is_empty() {
+        return Err(ErrorKind::CreateEmailClientErr("missing recipients".to_string()));
+    }
+
+    let builder = match settings.tls {
+        SmtpTls::Starttls => SmtpTransport::starttls_relay(&settings.host),
+        SmtpTls::Tls => SmtpTransport::relay(&settings.host),
+        SmtpTls::None => Ok(SmtpTransport::builder_dangerous(&settings.host)),
+    }
+    .map_err(|e| ErrorKind::CreateEmailClientErr(e.to_string()))?
+    .port(settings.port.unwrap_or_else(|| settings.tls.default_port()))
+    .timeout(Some(Duration::from_secs(settings.timeout.into())));
+    let builder = match settings.username.is_empty() {
+        true => builder,
+        false => builder.credentials(Credentials::new(
+            settings.username.clone(),
+            settings.password.clone(),
+        )),
+    };
+
+    Ok(EmailMessenger {
+        transport: builder.build(),
+        from: mailbox(&settings.from)?,
+        to: settings
+            .to
+            .iter()
+            .map(String::as_str)
+            .map(mailbox)
+            .collect::<Result<_, _>>()?,
+        subject_prefix: settings.subject_prefix.clone(),
+        clip_url: settings.clip_url.trim_end_matches('/').to_string(),
+    })
+}
+
+impl EmailMessenger {
+    /// Return the link to the clip: under `clip_url` if set, otherwise the local path.
+    fn clip_link(&self, clip: &Path) -> String {
+        match (self.clip_url.is_empty(), clip.file_name()) {
+            (false, Some(name)) => format!("{}/{}", self.clip_url, name.to_string_lossy()),
+            _ => clip.display().to_string(),
+        }
+    }
+
+    /// Build the email of the alert.
+    fn message(&self, alert: &Alert) -> Result<Message, ErrorKind> {
+        let mut subject = format!("{}: {}", alert.camera, alert.title);
+        if !self.subject_prefix.is_empty() {
+            subject = format!("{} {subject}", self.subject_prefix);
+        }
+
+        let mut text = alert.text();
+        if let Some(clip) = &alert.clip {
+            text.push_str(&format!("\n\nClip: {}", self.clip_link(clip)));
+        }
+
+        let mut builder = Message::builder().from(self.from.clone()).subject(subject);
+        for to in &self.to {
+            builder = builder.to(to.clone());
+        }
+
+        let mut body = MultiPart::mixed().singlepart(SinglePart::plain(text));
+        for attachment in &alert.attachments {
+            let content_type = ContentType::parse(&attachment.content_type)
+                .map_err(|e| ErrorKind::UnableToSendEmail(e.to_string()))?;
+            body = body.singlepart(
+                Attachment::new(attachment.name.clone()).body(attachment.data.clone(), content_type),
+            );
+        }
+
+        builder
+            .multipart(body)
+            .map_err(|e| ErrorKind::UnableToSendEmail(e.to_string()))
+    }
+}
+
+impl Messenger for EmailMessenger {
+    fn name(&self) -> &str {
+        "email"
+    }
+
+    fn send(&mut self, alert: &Alert) -> Result<(), ErrorKind> {
+        let message = self.message(alert)?;
+        self.transport
+            .send(&message)
+            .map_err(|e| ErrorKind::UnableToSendEmail(e.to_string()))?;
+
+        Ok(())
+    }
+}
@@ -48,6 +48,48 @@ pub enum ErrorKind {
     CreateSlackPayloadErr,
     /// Occurs when unable to send Slack message
     UnableToSendSlackMessage(String),
+    /// Occurs when unable to write the motion detection mask preview
+    MaskPreviewErr(String),
+    /// Occurs when the motion detection algorithm fails to process a frame
+    MotionAlgorithmErr(String),
+    /// Occurs when the video codec/container pair can't be written by the OpenCV backend
+    UnsupportedCodec(String),
+    /// Occurs when the output disk is critically full and no new clip can be started
+    DiskFull(String),
+    /// Occurs when the retention manager is unable to inspect the output directory
+    RetentionErr(String),
+    /// Occurs when unable to write the clip metadata sidecar
+    SidecarErr(String),
+    /// Occurs when the event database can't be opened, written or queried
+    EventDbErr(String),
+    /// Occurs when unable to encode or save an event snapshot
+    SnapshotErr(String),
+    /// Occurs when the webhook is misconfigured or unable to deliver an event
+    WebhookErr(String),
+    /// Occurs when an alert can't be delivered by a messenger
+    AlertErr(String),
+    /// Occurs when the Telegram messenger is misconfigured
+    CreateTelegramClientErr(String),
+    /// Occurs when unable to send Telegram message
+    UnableToSendTelegramMessage(String),
+    /// Occurs when unable to create Discord client for Messenger
+    CreateDiscordClientErr(String),
+    /// Occurs when unable to send Discord message
+    UnableToSendDiscordMessage(String),
+    /// Occurs when unable to create Matrix client for Messenger
+    CreateMatrixClientErr(String),
+    /// Occurs when unable to send Matrix message
+    UnableToSendMatrixMessage(String),
+    /// Occurs when the email messenger is misconfigured
+    CreateEmailClientErr(String),
+    /// Occurs when unable to send email
+    UnableToSendEmail(String),
+    /// Occurs when the MQTT publisher is misconfigured or unable to queue a message
+    MqttErr(String),
+    /// Occurs when a shell hook can't be started, fails or times out
+    HookErr(String),
+    /// Occurs when the schedule settings or the arming state file are invalid
+    ScheduleErr(String),
 }
 
 impl Display for ErrorKind {
@@ -66,6 +108,27 @@ impl Display for ErrorKind {
             Self::CreateSlackClientErr(msg) => Some(msg.to_string()),
             Self::CreateSlackPayloadErr => Some("unable to create Slack payload".to_string()),
             Self::UnableToSendSlackMessage(msg) => Some(msg.to_string()),
+            Self::MaskPreviewErr(msg) => Some(msg.to_string()),
+            Self::MotionAlgorithmErr(msg) => Some(msg.to_string()),
+            Self::UnsupportedCodec(msg) => Some(msg.to_string()),
+            Self::DiskFull(msg) => Some(msg.to_string()),
+            Self::RetentionErr(msg) => Some(msg.to_string()),
+            Self::SidecarErr(msg) => Some(msg.to_string()),
+            Self::EventDbErr(msg) => Some(msg.to_string()),
+            Self::SnapshotErr(msg) => Some(msg.to_string()),
+            Self::WebhookErr(msg) => Some(msg.to_string()),
+            Self::AlertErr(msg) => Some(msg.to_string()),
+            Self::CreateTelegramClientErr(msg) => Some(msg.to_string()),
+            Self::UnableToSendTelegramMessage(msg) => Some(msg.to_string()),
+            Self::CreateDiscordClientErr(msg) => Some(msg.to_string()),
+            Self::UnableToSendDiscordMessage(msg) => Some(msg.to_string()),
+            Self::CreateMatrixClientErr(msg) => Some(msg.to_string()),
+            Self::UnableToSendMatrixMessage(msg) => Some(msg.to_string()),
+            Self::CreateEmailClientErr(msg) => Some(msg.to_string()),
+            Self::UnableToSendEmail(msg) => Some(msg.to_string()),
+            Self::MqttErr(msg) => Some(msg.to_string()),
+            Self::HookErr(msg) => Some(msg.to_string()),
+            Self::ScheduleErr(msg) => Some(msg.to_string()),
         }
         .unwrap_or_default()
         .fmt(f)
@@ -0,0 +1,460 @@
+// rustymode: Fork of bombuscv, originally an OpenCV-based motion detection/recording software built for research on bumblebees.
+// Originally developed as bombuscv by Marco Radocchia (C) 2022
+// Modified and renamed to rustymode by Dmitry Sobolev (C) 2025
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use crate::{error::ErrorKind, Detection};
+use chrono::{DateTime, Local, TimeZone};
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Event database file name, inside the output directory.
+pub const EVENTS_DB: &str = "events.db";
+
+/// Event database schema.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    camera TEXT NOT NULL,
+    start INTEGER NOT NULL,
+    end INTEGER,
+    peak_score REAL NOT NULL DEFAULT 0,
+    zones TEXT NOT NULL DEFAULT '[]',
+    clip TEXT,
+    snapshot TEXT
+);
+CREATE INDEX IF NOT EXISTS events_start ON events (start);
+CREATE TABLE IF NOT EXISTS alerts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    event_id INTEGER REFERENCES events (id),
+    backend TEXT NOT NULL,
+    sent_at INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    error TEXT
+);
+CREATE INDEX IF NOT EXISTS alerts_event_id ON alerts (event_id);
+";
+
+/// Motion event: consecutive motion frames, until no motion is detected for the post-roll
+/// duration.
+///
+/// # Fields
+/// * id: event database row id, if recorded
+/// * camera: camera name
+/// * start: DateTime of the first motion frame
+/// * end: DateTime of the last motion frame
+/// * peak_score: highest motion score of the event
+/// * zones: names of the motion detection zones touched by the event
+/// * snapshot: event snapshot path, once saved
+#[derive(Debug, Clone)]
+pub struct MotionEvent {
+    pub id: Option<i64>,
+    pub camera: String,
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+    pub peak_score: f64,
+    pub zones: BTreeSet<String>,
+    pub snapshot: Option<PathBuf>,
+}
+
+impl MotionEvent {
+    /// Return the event duration.
+    pub fn duration(&self) -> chrono::Duration {
+        self.end - self.start
+    }
+}
+
+/// Motion event state change.
+#[derive(Debug)]
+pub enum EventChange {
+    /// A new motion event started: see `EventTracker::current_mut`.
+    Started,
+    /// The motion event is over.
+    Ended(MotionEvent),
+}
+
+/// Group motion detections into motion events.
+///
+/// # Fields
+/// * camera: camera name
+/// * post_roll: duration without motion after which the event is over
+/// * current: motion event in progress
+pub struct EventTracker {
+    camera: String,
+    post_roll: chrono::Duration,
+    current: Option<MotionEvent>,
+}
+
+impl EventTracker {
+    /// Create an instance of the event tracker.
+    ///
+    /// # Parameters
+    /// * camera: camera name
+    /// * post_roll: duration without motion after which the event is over
+    pub fn new(camera: &str, post_roll: chrono::Duration) -> Self {
+        Self {
+            camera: camera.to_string(),
+            post_roll,
+            current: None,
+        }
+    }
+
+    /// Account for the motion detection result of a frame, returning the event state change, if
+    /// any.
+    pub fn update(
+        &mut self,
+        datetime: DateTime<Local>,
+        detection: Option<&Detection>,
+    ) -> Option<EventChange> {
+        let Some(detection) = detection else {
+            // No motion for the whole post-roll: the event is over.
+            let expired = self
+                .current
+                .as_ref()
+                .is_some_and(|event| datetime - event.end > self.post_roll);
+            return if expired {
+                self.finish().map(EventChange::Ended)
+            } else {
+                None
+            };
+        };
+
+        // Motion goes on.
+        if let Some(event) = &mut self.current {
+            event.end = datetime;
+            event.peak_score = event.peak_score.max(detection.score);
+            event.zones.extend(detection.zones.iter().cloned());
+            return None;
+        }
+
+        // Motion started.
+        self.current = Some(MotionEvent {
+            id: None,
+            camera: self.camera.clone(),
+            start: datetime,
+            end: datetime,
+            peak_score: detection.score,
+            zones: detection.zones.iter().cloned().collect(),
+            snapshot: None,
+        });
+        Some(EventChange::Started)
+    }
+
+    /// Return the camera name.
+    pub fn camera(&self) -> &str {
+        &self.camera
+    }
+
+    /// Return the motion event in progress, if any.
+    pub fn current(&self) -> Option<&MotionEvent> {
+        self.current.as_ref()
+    }
+
+    /// Return the motion event in progress, if any, for update.
+    pub fn current_mut(&mut self) -> Option<&mut MotionEvent> {
+        self.current.as_mut()
+    }
+
+    /// End the motion event in progress, if any (e.g. on termination).
+    pub fn finish(&mut self) -> Option<MotionEvent> {
+        self.current.take()
+    }
+}
+
+/// Alert delivery record.
+///
+/// # Fields
+/// * backend: messenger backend name
+/// * sent_at: delivery attempt date&time
+/// * status: delivery status (`sent` or `failed`)
+/// * error: delivery error, if failed
+#[derive(Serialize, Debug, Clone)]
+pub struct AlertRecord {
+    pub backend: String,
+    pub sent_at: String,
+    pub status: String,
+    pub error: Option<String>,
+}
+
+/// Motion event record, as stored in the event database.
+///
+/// # Fields
+/// * id: event id
+/// * camera: camera name
+/// * start: event start date&time
+/// * end: event end date&time (`None` if the event was still in progress)
+/// * duration: event duration in seconds
+/// * peak_score: highest motion score of the event
+/// * zones: motion detection zones touched by the event
+/// * clip: clip containing the event
+/// * snapshot: event snapshot
+/// * alerts: alerts sent for the event
+#[derive(Serialize, Debug, Clone)]
+pub struct EventRecord {
+    pub id: i64,
+    pub camera: String,
+    pub start: String,
+    pub end: Option<String>,
+    pub duration: Option<f64>,
+    pub peak_score: f64,
+    pub zones: Vec<String>,
+    pub clip: Option<String>,
+    pub snapshot: Option<String>,
+    pub alerts: Vec<AlertRecord>,
+}
+
+/// Event database query filter.
+///
+/// # Fields
+/// * since: events started at or after this date&time
+/// * until: events started at or before this date&time
+/// * camera: events of this camera only
+/// * min_score: events with at least this peak score
+#[derive(Debug, Default, Clone)]
+pub struct EventFilter {
+    pub since: Option<DateTime<Local>>,
+    pub until: Option<DateTime<Local>>,
+    pub camera: Option<String>,
+    pub min_score: f64,
+}
+
+/// Convert a database timestamp (milliseconds since epoch) to a RFC 3339 date&time.
+fn rfc3339(millis: i64) -> String {
+    Local
+        .timestamp_millis_opt(millis)
+        .single()
+        .map(|datetime| datetime.to_rfc3339())
+        .unwrap_or_default()
+}
+
+/// Map SQLite errors to the event database error kind.
+fn db_err(e: rusqlite::Error) -> ErrorKind {
+    ErrorKind::EventDbErr(e.to_string())
+}
+
+/// Local SQLite database of motion events and alerts.
+///
+/// # Fields
+/// * conn: SQLite connection
+pub struct EventDb {
+    conn: Connection,
+}
+
+impl EventDb {
+    /// Open (creating it if needed) the event database inside the given directory.
+    ///
+    /// Each thread should open its own instance: concurrent writers wait for each other.
+    pub fn open(directory: &Path) -> Result<Self, ErrorKind> {
+        let conn = Connection::open(directory.join(EVENTS_DB)).map_err(db_err)?;
+        conn.busy_timeout(Duration::from_secs(5)).map_err(db_err)?;
+        conn.execute_batch(SCHEMA).map_err(db_err)?;
+
+        Ok(Self { conn })
+    }
+
+    /// Record the start of a motion event, returning its id.
+    pub fn insert_event(&self, event: &MotionEvent) -> Result<i64, ErrorKind> {
+        self.conn
+            .execute(
+                "INSERT INTO events (camera, start, peak_score) VALUES (?1, ?2, ?3)",
+                params![event.camera, event.start.timestamp_millis(), event.peak_score],
+            )
+            .map_err(db_err)?;
+
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Record the end of a motion event.
+    pub fn finish_event(&self, event: &MotionEvent) -> Result<(), ErrorKind> {
+        let Some(id) = event.id else {
+            return Ok(());
+        };
+        let zones = serde_json::to_string(&event.zones).map_err(|e| {
+            ErrorKind::EventDbErr(e.to_string())
+        })?;
+        self.conn
+            .execute(
+                "UPDATE events SET end = ?1, peak_score = ?2, zones = ?3, snapshot = ?4
+                 WHERE id = ?5",
+                params![
+                    event.end.timestamp_millis(),
+                    event.peak_score,
+                    zones,
+                    event
+                        .snapshot
+                        .as_ref()
+                        .map(|snapshot| snapshot.to_string_lossy()),
+                    id
+                ],
+            )
+            .map_err(db_err)?;
+
+        Ok(())
+    }
+
+    /// Attach a finalized clip to the events of the camera started after `since` (the previous
+    /// clip finalization) and up to `until` that have no clip yet.
+    ///
+    /// An event starting on the finalization frame belongs to the finalized clip, as paired by
+    /// the notifier.
+    ///
+    /// The lower bound keeps the events whose clip was deleted by the retention policy from
+    /// being attached to a newer clip.
+    pub fn attach_clip(
+        &self,
+        camera: &str,
+        clip: &Path,
+        since: DateTime<Local>,
+        until: DateTime<Local>,
+    ) -> Result<(), ErrorKind> {
+        self.conn
+            .execute(
+                "UPDATE events SET clip = ?1
+                 WHERE clip IS NULL AND camera = ?2 AND start > ?3 AND start <= ?4",
+                params![
+                    clip.to_string_lossy(),
+                    camera,
+                    since.timestamp_millis(),
+                    until.timestamp_millis()
+                ],
+            )
+            .map_err(db_err)?;
+
+        Ok(())
+    }
+
+    /// Return the clip & snapshot files referenced by the events.
+    pub fn files(&self) -> Result<Vec<PathBuf>, ErrorKind> {
+        let mut files = self
+            .conn
+            .prepare(
+                "SELECT clip FROM events WHERE clip IS NOT NULL
+                 UNION SELECT snapshot FROM events WHERE snapshot IS NOT NULL",
+            )
+            .map_err(db_err)?;
+        let rows = files
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(db_err)?;
+
+        rows.map(|row| row.map(PathBuf::from).map_err(db_err))
+            .collect()
+    }
+
+    /// Clear the references to a deleted clip or snapshot file.
+    pub fn forget_file(&self, file: &Path) -> Result<(), ErrorKind> {
+        let file = file.to_string_lossy();
+        self.conn
+            .execute("UPDATE events SET clip = NULL WHERE clip = ?1", params![file])
+            .map_err(db_err)?;
+        self.conn
+            .execute("UPDATE events SET snapshot = NULL WHERE snapshot = ?1", params![file])
+            .map_err(db_err)?;
+
+        Ok(())
+    }
+
+    /// Record an alert delivery attempt.
+    pub fn insert_alert(
+        &self,
+        event_id: Option<i64>,
+        backend: &str,
+        sent_at: DateTime<Local>,
+        error: Option<&str>,
+    ) -> Result<(), ErrorKind> {
+        let status = if error.is_none() { "sent" } else { "failed" };
+        self.conn
+            .execute(
+                "INSERT INTO alerts (event_id, backend, sent_at, status, error)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![event_id, backend, sent_at.timestamp_millis(), status, error],
+            )
+            .map_err(db_err)?;
+
+        Ok(())
+    }
+
+    /// Return the events matching the filter, oldest first.
+    pub fn query(&self, filter: &EventFilter) -> Result<Vec<EventRecord>, ErrorKind> {
+        let mut events = self
+            .conn
+            .prepare(
+                "SELECT id, camera, start, end, peak_score, zones, clip, snapshot FROM events
+                 WHERE (?1 IS NULL OR start >= ?1) AND (?2 IS NULL OR start <= ?2)
+                 AND (?3 IS NULL OR camera = ?3) AND peak_score >= ?4
+                 ORDER BY start",
+            )
+            .map_err(db_err)?;
+        let mut alerts = self
+            .conn
+            .prepare(
+                "SELECT backend, sent_at, status, error FROM alerts
+                 WHERE event_id = ?1 ORDER BY sent_at",
+            )
+            .map_err(db_err)?;
+
+        let rows = events
+            .query_map(
+                params![
+                    filter.since.map(|since| since.timestamp_millis()),
+                    filter.until.map(|until| until.timestamp_millis()),
+                    filter.camera,
+                    filter.min_score,
+                ],
+                |row| {
+                    let start: i64 = row.get(2)?;
+                    let end: Option<i64> = row.get(3)?;
+                    let zones: String = row.get(5)?;
+                    Ok(EventRecord {
+                        id: row.get(0)?,
+                        camera: row.get(1)?,
+                        start: rfc3339(start),
+                        end: end.map(rfc3339),
+                        duration: end.map(|end| (end - start) as f64 / 1000.),
+                        peak_score: row.get(4)?,
+                        zones: serde_json::from_str(&zones).unwrap_or_default(),
+                        clip: row.get(6)?,
+                        snapshot: row.get(7)?,
+                        alerts: Vec::new(),
+                    })
+                },
+            )
+            .map_err(db_err)?;
+
+        let mut records = Vec::new();
+        for row in rows {
+            let mut record = row.map_err(db_err)?;
+            record.alerts = alerts
+                .query_map(params![record.id], |row| {
+                    Ok(AlertRecord {
+                        backend: row.get(0)?,
+                        sent_at: rfc3339(row.get(1)?),
+                        status: row.get(2)?,
+                        error: row.get(3)?,
+                    })
+                })
+                .map_err(db_err)?
+                .collect::<Result<_, _>>()
+                .map_err(db_err)?;
+            records.push(record);
+        }
+
+        Ok(records)
+    }
+}
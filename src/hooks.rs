@@ -0,0 +1,312 @@
+// rustymode: Fork of bombuscv, originally an OpenCV-based motion detection/recording software built for research on bumblebees.
+// Originally developed as bombuscv by Marco Radocchia (C) 2022
+// Modified and renamed to rustymode by Dmitry Sobolev (C) 2025
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use crate::{config::deserialize_positive, error::ErrorKind, events::MotionEvent};
+use chrono::{DateTime, Local};
+use serde::Deserialize;
+use std::{
+    fmt::{self, Display, Formatter},
+    os::unix::process::CommandExt,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Interval between two checks of a running hook.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Shell hooks settings (`[hooks]` table).
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct HookSettings {
+    /// Command run when a motion event starts (empty for none).
+    pub on_event_start: String,
+
+    /// Command run when a motion event ends (empty for none).
+    pub on_event_end: String,
+
+    /// Command run when a clip is finalized (empty for none).
+    pub on_clip_finalized: String,
+
+    /// Time in seconds after which a running hook is killed (at least 1).
+    #[serde(deserialize_with = "deserialize_positive")]
+    pub timeout: u16,
+
+    /// Maximum number of hooks running at once: further hooks are skipped.
+    pub max_concurrent: usize,
+}
+
+/// Implement the Default trait for HookSettings.
+impl Default for HookSettings {
+    /// Default hooks settings.
+    fn default() -> Self {
+        Self {
+            on_event_start: String::new(),
+            on_event_end: String::new(),
+            on_clip_finalized: String::new(),
+            timeout: 30,
+            max_concurrent: 4,
+        }
+    }
+}
+
+impl HookSettings {
+    /// Return the command of the hook.
+    pub fn command(&self, hook: Hook) -> &str {
+        match hook {
+            Hook::EventStart => &self.on_event_start,
+            Hook::EventEnd => &self.on_event_end,
+            Hook::ClipFinalized => &self.on_clip_finalized,
+        }
+    }
+}
+
+/// Hook points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    /// A motion event started.
+    EventStart,
+    /// A motion event ended.
+    EventEnd,
+    /// A clip has been finalized.
+    ClipFinalized,
+}
+
+impl Display for Hook {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EventStart => write!(f, "on_event_start"),
+            Self::EventEnd => write!(f, "on_event_end"),
+            Self::ClipFinalized => write!(f, "on_clip_finalized"),
+        }
+    }
+}
+
+/// Details handed to a hook, as `RUSTYMODE_*` environment variables.
+///
+/// # Fields
+/// * camera: camera name
+/// * timestamp: event start or clip finalization DateTime
+/// * event_id: event database id of the motion event
+/// * score: peak motion score of the event
+/// * duration: duration of the ended event
+/// * clip: finalized clip path
+/// * snapshot: event snapshot path
+#[derive(Debug, Clone, Default)]
+pub struct HookEvent {
+    pub camera: String,
+    pub timestamp: Option<DateTime<Local>>,
+    pub event_id: Option<i64>,
+    pub score: Option<f64>,
+    pub duration: Option<chrono::Duration>,
+    pub clip: Option<PathBuf>,
+    pub snapshot: Option<PathBuf>,
+}
+
+impl HookEvent {
+    /// Return the details of a motion event.
+    pub fn event(event: &MotionEvent, ended: bool) -> Self {
+        Self {
+            camera: event.camera.clone(),
+            timestamp: Some(event.start),
+            event_id: event.id,
+            score: Some(event.peak_score),
+            duration: ended.then(|| event.duration()),
+            clip: None,
+            snapshot: event.snapshot.clone(),
+        }
+    }
+
+    /// Return the details of a finalized clip.
+    pub fn clip(camera: &str, timestamp: DateTime<Local>, clip: &Path) -> Self {
+        Self {
+            camera: camera.to_string(),
+            timestamp: Some(timestamp),
+            clip: Some(clip.to_path_buf()),
+            ..Default::default()
+        }
+    }
+
+    /// Return the environment variables of the hook: unknown details are set empty.
+    pub fn env(&self, hook: Hook) -> Vec<(&'static str, String)> {
+        let path = |path: &Option<PathBuf>| {
+            path.as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_default()
+        };
+        vec![
+            ("RUSTYMODE_HOOK", hook.to_string()),
+            ("RUSTYMODE_CAMERA", self.camera.clone()),
+            (
+                "RUSTYMODE_TIMESTAMP",
+                self.timestamp.map(|ts| ts.to_rfc3339()).unwrap_or_default(),
+            ),
+            (
+                "RUSTYMODE_EVENT_ID",
+                self.event_id.map(|id| id.to_string()).unwrap_or_default(),
+            ),
+            (
+                "RUSTYMODE_SCORE",
+                self.score.map(|score| format!("{score:.3}")).unwrap_or_default(),
+            ),
+            (
+                "RUSTYMODE_DURATION",
+                self.duration
+                    .map(|duration| format!("{:.1}", duration.num_milliseconds() as f64 / 1000.))
+                    .unwrap_or_default(),
+            ),
+            ("RUSTYMODE_CLIP", path(&self.clip)),
+            ("RUSTYMODE_SNAPSHOT", path(&self.snapshot)),
+        ]
+    }
+}
+
+/// Shell hooks runner: runs the hook commands with `sh -c` in the background, so the caller
+/// never waits for them.
+///
+/// # Fields
+/// * settings: hooks settings
+/// * running: number of hooks running
+/// * report: callback reporting failed, killed or skipped hooks
+#[derive(Clone)]
+pub struct HookRunner {
+    settings: Arc<HookSettings>,
+    running: Arc<AtomicUsize>,
+    report: Arc<dyn Fn(ErrorKind) + Send + Sync>,
+}
+
+impl HookRunner {
+    /// Create an instance of the hooks runner.
+    ///
+    /// # Parameters
+    /// * settings: hooks settings
+    /// * report: callback reporting errors of the hooks running in the background
+    pub fn new(
+        settings: &HookSettings,
+        report: impl Fn(ErrorKind) + Send + Sync + 'static,
+    ) -> Result<Self, ErrorKind> {
+        if settings.max_concurrent == 0 {
+            return Err(ErrorKind::HookErr(
+                "max_concurrent must be at least 1".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            settings: Arc::new(settings.clone()),
+            running: Arc::new(AtomicUsize::new(0)),
+            report: Arc::new(report),
+        })
+    }
+
+    /// Return the names of the configured hooks.
+    pub fn names(&self) -> Vec<String> {
+        [Hook::EventStart, Hook::EventEnd, Hook::ClipFinalized]
+            .into_iter()
+            .filter(|hook| !self.settings.command(*hook).is_empty())
+            .map(|hook| hook.to_string())
+            .collect()
+    }
+
+    /// Return the number of hooks running.
+    pub fn running(&self) -> usize {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// Start the hook command, if configured, without waiting for it. The hook is skipped if
+    /// `max_concurrent` hooks are already running.
+    pub fn run(&self, hook: Hook, event: &HookEvent) -> Result<(), ErrorKind> {
+        let command = self.settings.command(hook);
+        if command.is_empty() {
+            return Ok(());
+        }
+
+        let max = self.settings.max_concurrent;
+        if self
+            .running
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| (n < max).then_some(n + 1))
+            .is_err()
+        {
+            return Err(ErrorKind::HookErr(format!(
+                "{hook} skipped: {max} hooks already running"
+            )));
+        }
+
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .envs(event.env(hook))
+            .stdin(Stdio::null())
+            // Own process group: the commands started by the hook are killed along with it.
+            .process_group(0)
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) => {
+                self.running.fetch_sub(1, Ordering::SeqCst);
+                return Err(ErrorKind::HookErr(format!("{hook}: {e}")));
+            }
+        };
+
+        let runner = self.clone();
+        let timeout = Duration::from_secs(self.settings.timeout.into());
+        thread::spawn(move || {
+            if let Err(e) = wait(hook, &mut child, timeout) {
+                (runner.report)(e);
+            }
+            runner.running.fetch_sub(1, Ordering::SeqCst);
+        });
+
+        Ok(())
+    }
+
+    /// Wait for the running hooks to exit (or to be killed on timeout).
+    pub fn wait(&self) {
+        while self.running() > 0 {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+/// Wait for the hook to exit, killing its whole process group on timeout.
+fn wait(hook: Hook, child: &mut Child, timeout: Duration) -> Result<(), ErrorKind> {
+    let start = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return Ok(()),
+            Ok(Some(status)) => return Err(ErrorKind::HookErr(format!("{hook}: {status}"))),
+            Ok(None) if start.elapsed() >= timeout => {
+                // SAFETY: plain syscall; the group id is the hook shell pid (`process_group(0)`),
+                // not reaped yet, so it can't have been reused.
+                unsafe {
+                    libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+                }
+                let _ = child.wait();
+                return Err(ErrorKind::HookErr(format!(
+                    "{hook}: killed after {}s timeout",
+                    timeout.as_secs()
+                )));
+            }
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(e) => return Err(ErrorKind::HookErr(format!("{hook}: {e}"))),
+        }
+    }
+}
@@ -21,42 +21,98 @@
 //! Motion Detection, Video Streaming and Alerting with Rust.
 //!
 
+pub mod alert;
+pub mod algorithm;
 pub mod args;
 pub mod color;
 pub mod config;
+pub mod discord;
+pub mod email;
 pub mod error;
+pub mod events;
+pub mod hooks;
+pub mod matrix;
+pub mod metadata;
+pub mod mqtt;
+pub mod multipart;
+pub mod notifier;
+pub mod retention;
+pub mod schedule;
+#[cfg(feature = "slack")]
 pub mod slack;
+pub mod snapshot;
+pub mod telegram;
+pub mod webhook;
+pub mod zone;
 
-use crate::error::ErrorKind;
+use crate::{
+    alert::Alert,
+    algorithm::MotionAlgorithm,
+    config::{DetectorSettings, RecordingMode, RecordingSettings},
+    error::ErrorKind,
+    metadata::ClipMetadata,
+    retention::Retention,
+};
 use chrono::{DateTime, Local};
 use opencv::{
-    core::{absdiff, Point, Scalar, Size, Vector, BORDER_CONSTANT, BORDER_DEFAULT, CV_8UC3},
+    core::{bitwise_and, count_non_zero, mean, Point, Rect, Scalar, Size, Vector, BORDER_CONSTANT, BORDER_DEFAULT},
     imgproc::{
-        cvt_color, dilate, find_contours, gaussian_blur, morphology_default_border_value, put_text,
-        resize, threshold, LineTypes, CHAIN_APPROX_SIMPLE, COLOR_BGR2GRAY, FONT_HERSHEY_DUPLEX,
+        bounding_rect, contour_area, dilate, find_contours, gaussian_blur, morphology_default_border_value, put_text,
+        resize, threshold, LineTypes, CHAIN_APPROX_SIMPLE, FONT_HERSHEY_DUPLEX,
         INTER_LINEAR, RETR_EXTERNAL, THRESH_BINARY,
     },
     prelude::{Mat, MatTraitConst},
     videoio::{
         VideoCapture, VideoCaptureTrait, VideoCaptureTraitConst, VideoWriter, VideoWriterTrait,
+        VideoWriterTraitConst,
         CAP_FFMPEG, CAP_PROP_FPS, CAP_PROP_FRAME_HEIGHT, CAP_PROP_FRAME_WIDTH, CAP_V4L2, CAP_ANY,
     },
     highgui,
 };
 
-use std::{os::raw::c_char, path::Path};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fs,
+    mem,
+    os::raw::c_char,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 use std::io;
 use std::net::{SocketAddr, TcpListener};
-use slack_hook::{Payload, PayloadBuilder, Slack};
 use url::Url;
 
 /// Video codecs.
-#[derive(Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
 pub enum Codec {
     MJPG,
     XVID,
     MP4V,
     H264,
+    AVC1,
+    VP80,
+    VP90,
+}
+
+impl FromStr for Codec {
+    type Err = String;
+
+    fn from_str(codec: &str) -> Result<Self, Self::Err> {
+        match codec {
+            "mjpg" => Ok(Self::MJPG),
+            "xvid" => Ok(Self::XVID),
+            "mp4v" => Ok(Self::MP4V),
+            "h264" => Ok(Self::H264),
+            "avc1" => Ok(Self::AVC1),
+            "vp80" => Ok(Self::VP80),
+            "vp90" => Ok(Self::VP90),
+            _ => Err(format!(
+                "unknown codec '{codec}' (valid: mjpg, xvid, mp4v, h264, avc1, vp80, vp90)"
+            )),
+        }
+    }
 }
 
 impl Codec {
@@ -76,9 +132,111 @@ impl Codec {
             Codec::H264 => {
                 VideoWriter::fourcc('h' as char, '2' as char, '6' as char, '4' as char)
             }
+            Codec::AVC1 => {
+                VideoWriter::fourcc('a' as char, 'v' as char, 'c' as char, '1' as char)
+            }
+            Codec::VP80 => {
+                VideoWriter::fourcc('V' as char, 'P' as char, '8' as char, '0' as char)
+            }
+            Codec::VP90 => {
+                VideoWriter::fourcc('V' as char, 'P' as char, '9' as char, '0' as char)
+            }
         }
         .unwrap_or_else(|_| panic!("unable to generate {:?} fourcc code", self))
     }
+
+    /// Return whether the video codec can be stored in the given container.
+    pub fn supports(&self, container: Container) -> bool {
+        match *self {
+            Codec::MJPG => matches!(container, Container::Avi | Container::Mkv),
+            Codec::XVID | Codec::MP4V => {
+                matches!(container, Container::Avi | Container::Mkv | Container::Mp4)
+            }
+            Codec::H264 | Codec::AVC1 => matches!(container, Container::Mkv | Container::Mp4),
+            Codec::VP80 | Codec::VP90 => matches!(container, Container::Mkv | Container::Webm),
+        }
+    }
+
+    /// Check that the available OpenCV backend can write a video with this codec to the given
+    /// container, opening (and removing) a probe file in `directory`.
+    pub fn validate(
+        &self,
+        container: Container,
+        directory: &Path,
+        fps: f64,
+        size: Size,
+    ) -> Result<(), ErrorKind> {
+        let unsupported = || {
+            ErrorKind::UnsupportedCodec(format!(
+                "{:?} codec can't be written to .{} container by the available OpenCV backend",
+                self,
+                container.extension()
+            ))
+        };
+
+        if !self.supports(container) {
+            return Err(unsupported());
+        }
+
+        let probe = directory.join(format!(".rustymode-probe.{}", container.extension()));
+        let opened = VideoWriter::new(
+            probe.to_str().expect("invalid UTF-8 video path"),
+            self.fourcc(),
+            fps,
+            size,
+            true,
+        )
+        .and_then(|mut writer| {
+            let opened = writer.is_opened()?;
+            writer.release()?;
+            Ok(opened)
+        });
+        // Probe file may not even exist: nothing to clean up then.
+        let _ = fs::remove_file(&probe);
+
+        match opened {
+            Ok(true) => Ok(()),
+            _ => Err(unsupported()),
+        }
+    }
+}
+
+/// Video containers.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Container {
+    Mkv,
+    Mp4,
+    Avi,
+    Webm,
+}
+
+impl FromStr for Container {
+    type Err = String;
+
+    fn from_str(container: &str) -> Result<Self, Self::Err> {
+        match container {
+            "mkv" => Ok(Self::Mkv),
+            "mp4" => Ok(Self::Mp4),
+            "avi" => Ok(Self::Avi),
+            "webm" => Ok(Self::Webm),
+            _ => Err(format!(
+                "unknown container '{container}' (valid: mkv, mp4, avi, webm)"
+            )),
+        }
+    }
+}
+
+impl Container {
+    /// Return the file extension associated to the video container.
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            Container::Mkv => "mkv",
+            Container::Mp4 => "mp4",
+            Container::Avi => "avi",
+            Container::Webm => "webm",
+        }
+    }
 }
 
 /// Captured Frame.
@@ -92,6 +250,59 @@ pub struct Frame {
     pub datetime: DateTime<Local>,
 }
 
+/// Bounding box of a motion area.
+///
+/// # Fields
+/// * x: top-left corner x coordinate
+/// * y: top-left corner y coordinate
+/// * width: box width
+/// * height: box height
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoundingBox {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl BoundingBox {
+    /// Scale analysis frame rectangle to original frame coordinates.
+    fn scaled(rect: Rect, scale_x: f64, scale_y: f64) -> Self {
+        Self {
+            x: (f64::from(rect.x) * scale_x).round() as i32,
+            y: (f64::from(rect.y) * scale_y).round() as i32,
+            width: (f64::from(rect.width) * scale_x).round() as i32,
+            height: (f64::from(rect.height) * scale_y).round() as i32,
+        }
+    }
+}
+
+impl From<BoundingBox> for Rect {
+    fn from(bbox: BoundingBox) -> Self {
+        Rect::new(bbox.x, bbox.y, bbox.width, bbox.height)
+    }
+}
+
+/// Motion detection result.
+///
+/// # Fields
+/// * datetime: DateTime object representing the instant the frame was captured
+/// * boxes: bounding boxes of the qualifying contours, in original frame coordinates
+/// * area: total area of the qualifying contours, in original frame pixels
+/// * score: ratio of the motion detection zones (whole frame if none) covered by the qualifying
+///   contours (0-1)
+/// * contours: number of qualifying contours
+/// * zones: names of the include zones touched by the motion
+#[derive(Debug, Clone)]
+pub struct Detection {
+    pub datetime: DateTime<Local>,
+    pub boxes: Vec<BoundingBox>,
+    pub area: f64,
+    pub score: f64,
+    pub contours: usize,
+    pub zones: Vec<String>,
+}
+
 /// Video frame grabber.
 ///
 /// # Fields
@@ -195,36 +406,97 @@ impl Drop for Grabber {
     }
 }
 
+/// Motion detection statistics.
+///
+/// # Fields
+/// * frames: number of processed frames
+/// * detections: number of frames where motion was detected
+/// * lighting_events: number of frame-wide lighting changes suppressed
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DetectorStats {
+    pub frames: u64,
+    pub detections: u64,
+    pub lighting_events: u64,
+}
+
 /// Motion detector.
 ///
 /// # Fields
-/// * prev_frame: previous frame to make comparisons
-#[derive(Debug)]
+/// * algorithm: motion detection algorithm computing the frame difference
+/// * size: analysis frame size, derived from the first received frame
+/// * mask: zone mask applied to the frame difference
+/// * active_area: number of analysis frame pixels where motion is detected (not masked out)
+/// * zone_masks: masks of the named include zones, to report the zones touched by motion
+/// * settings: motion detection tuning parameters
+/// * luminance: mean luminance of the previous frame
+/// * lighting: whether the previous frame was part of a lighting event
+/// * stats: motion detection statistics
 pub struct MotionDetector {
-    prev_frame: Mat,
+    algorithm: Box<dyn MotionAlgorithm + Send>,
+    size: Option<Size>,
+    mask: Option<Mat>,
+    active_area: f64,
+    zone_masks: Vec<(String, Mat)>,
+    settings: DetectorSettings,
+    luminance: Option<f64>,
+    lighting: bool,
+    stats: DetectorStats,
 }
 
 impl Default for MotionDetector {
     fn default() -> Self {
-        Self::new()
+        // The default absdiff algorithm can't fail to initialize.
+        Self::new(DetectorSettings::default()).expect("unable to create motion detector")
     }
 }
 
 impl MotionDetector {
     /// Create an instance of the MotionDetector.
-    pub fn new() -> Self {
+    ///
+    /// # Parameters
+    /// * settings: motion detection tuning parameters
+    pub fn new(settings: DetectorSettings) -> Result<Self, ErrorKind> {
+        let algorithm = algorithm::new(&settings)?;
+        Ok(Self::with_algorithm(settings, algorithm))
+    }
+
+    /// Create an instance of the MotionDetector using a custom motion detection algorithm.
+    ///
+    /// # Parameters
+    /// * settings: motion detection tuning parameters
+    /// * algorithm: motion detection algorithm
+    pub fn with_algorithm(
+        settings: DetectorSettings,
+        algorithm: Box<dyn MotionAlgorithm + Send>,
+    ) -> Self {
         Self {
-            // Initialize prev_frame as 640x480 empty frame: next grabbed frames will be
-            // downscaled to this resolution and this initialization must be a valid Size for the
-            // first frame comparison.
-            prev_frame: unsafe { Mat::new_size(Size::new(640, 480), CV_8UC3).unwrap() },
+            algorithm,
+            size: None,
+            mask: None,
+            active_area: 1.,
+            zone_masks: Vec::new(),
+            settings,
+            luminance: None,
+            lighting: false,
+            stats: DetectorStats::default(),
         }
     }
 
+    /// Return the size frames are downscaled to before motion analysis: `analysis_width` wide
+    /// (never upscaling) and preserving the input frame aspect ratio.
+    fn analysis_size(&self, frame_size: Size) -> Size {
+        let width = frame_size.width.min(self.settings.analysis_width.into());
+        let height = (f64::from(frame_size.height) * f64::from(width)
+            / f64::from(frame_size.width))
+        .round() as i32;
+
+        Size::new(width, height.max(1))
+    }
+
     /// Receive grabbed frame and detect motion and returns:
-    /// - `Ok`: if `Some(Frame)` motion detected; if `None` no motion detected.
-    /// - `Err`: `frame` was empty and could not be processed.
-    pub fn detect_motion(&mut self, frame: Frame) -> Result<Option<Frame>, ErrorKind> {
+    /// - `Ok`: if `Some(Detection)` motion detected; if `None` no motion detected.
+    /// - `Err`: `frame` was empty or could not be processed.
+    pub fn detect_motion(&mut self, frame: &Frame) -> Result<Option<Detection>, ErrorKind> {
         // Create the resized_frame.
         let mut resized_frame = Mat::default();
 
@@ -240,44 +512,80 @@ impl MotionDetector {
             return Err(ErrorKind::EmptyFrame);
         }
 
-        // Downscale input frame (to 640x480) to reduce noise & computational weight.
+        // Derive the analysis size from the input frame: on the first frame or on input size
+        // change rebuild the zone mask and restart the background model.
+        let size = self.analysis_size(frame.frame.size().expect("unable to get frame size"));
+        let restart = self.size != Some(size);
+        if restart {
+            self.size = Some(size);
+            self.mask = zone::build_mask(&self.settings.zones, size);
+            self.active_area = f64::from(match &self.mask {
+                Some(mask) => count_non_zero(mask).expect("count_non_zero failed"),
+                None => size.area(),
+            })
+            .max(1.);
+            self.zone_masks = zone::build_zone_masks(&self.settings.zones, size);
+        }
+
+        // Downscale input frame (to analysis size) to reduce noise & computational weight.
         resize(
             &frame.frame,
             &mut resized_frame,
-            // WARNING: check if chaning the aspect ratio causes any problem.
-            Size::new(640, 480),
+            size,
             0.,
             0.,
             INTER_LINEAR,
         )
         .expect("frame resizing failed");
 
-        // Calculate absolute difference of pixel values.
-        absdiff(&self.prev_frame, &resized_frame, &mut frame_one).expect("absdiff failed");
+        self.stats.frames += 1;
 
-        // HELP: this are for graphical example
-        // highgui::imshow("bombuscv", &frame_one).unwrap();
-        // highgui::wait_key(1).unwrap();
+        // Mean luminance (ITU-R BT.601 weights over the BGR channel means) of the frame.
+        let bgr = mean(&resized_frame, &Mat::default()).expect("mean failed");
+        let luminance = 0.114 * bgr[0] + 0.587 * bgr[1] + 0.299 * bgr[2];
+        let luminance_jump = self
+            .luminance
+            .replace(luminance)
+            .map_or(0., |prev| (luminance - prev).abs());
 
-        // Update the previous frame.
-        self.prev_frame = resized_frame;
+        // Seed the restarted background model with this frame: there is nothing to compare it
+        // against yet (background subtractors report their whole first frame as foreground).
+        if restart {
+            self.algorithm.reset();
+            self.algorithm.apply(&resized_frame, &mut frame_two)?;
+            return Ok(None);
+        }
 
-        // Convert from BGR colorspace to grayscale.
-        cvt_color(
-            &frame_one,
-            &mut frame_two,
-            COLOR_BGR2GRAY, // Color space conversion code (see #ColorConversionCodes).
-            0, // Number of channels in the destination image; if the parameter is 0, the number of the channels is derived automatically from src and code.
-        )
-        .expect("cvt_color failed");
+        // Frame-wide brightness shift: restart the background model from this frame.
+        if self.settings.max_luminance_jump > 0. && luminance_jump > self.settings.max_luminance_jump
+        {
+            self.lighting_event();
+            self.algorithm.reset();
+            self.algorithm.apply(&resized_frame, &mut frame_two)?;
+            return Ok(None);
+        }
+
+        // Compute the grayscale foreground (difference) image.
+        self.algorithm.apply(&resized_frame, &mut frame_two)?;
+
+        // Mask out the frame difference outside of the motion detection zones.
+        if let Some(mask) = &self.mask {
+            bitwise_and(&frame_two, mask, &mut frame_one, &Mat::default())
+                .expect("bitwise_and failed");
+            mem::swap(&mut frame_one, &mut frame_two);
+        }
 
         // Apply gaussian blur.
         gaussian_blur(
             &frame_two,
             &mut frame_one,
-            Size::new(3, 3), // Kernel Size.
-            21.,             // Gaussian kernel standard deviation in x direction.
-            21.,             // Gaussian kernel standard deviation in y direction.
+            // Kernel Size.
+            Size::new(
+                self.settings.blur_kernel.into(),
+                self.settings.blur_kernel.into(),
+            ),
+            self.settings.blur_sigma, // Gaussian kernel standard deviation in x direction.
+            self.settings.blur_sigma, // Gaussian kernel standard deviation in y direction.
             BORDER_DEFAULT,
         )
         .expect("gaussian_blur failed");
@@ -286,19 +594,31 @@ impl MotionDetector {
         threshold(
             &frame_one,
             &mut frame_two,
-            30.,           // Threshold value.
+            self.settings.threshold.into(), // Threshold value.
             255., // Maximum value to use with the #THRESH_BINARY and #THRESH_BINARY_INV thresholding types.
             THRESH_BINARY, // Thresholding type (see #ThresholdType).
         )
         .expect("threshold failed");
 
+        // Nearly the whole detection area changed: most likely a lighting change rather than
+        // motion, so restart the background model instead of reporting it.
+        let changed_ratio = f64::from(count_non_zero(&frame_two).expect("count_non_zero failed"))
+            / self.active_area;
+        if changed_ratio > self.settings.max_changed_ratio {
+            self.lighting_event();
+            self.algorithm.reset();
+            self.algorithm.apply(&resized_frame, &mut frame_two)?;
+            return Ok(None);
+        }
+        self.lighting = false;
+
         // Dilate image.
         dilate(
             &frame_two,
             &mut frame_one,
             &Mat::default(), // Structuring element used for dilation; If elemenat=Mat(), a 3 x 3 rectangular structuring element is used.
             Point::new(-1, -1), // Position of the anchor within the element; default value (-1, -1) means that the anchor is at the element center.
-            3,                  // Number of times dilation is applied.
+            self.settings.dilate_iterations.into(), // Number of times dilation is applied.
             BORDER_CONSTANT,    // Pixel extrapolation method, see #BorderTypes.
             morphology_default_border_value().unwrap(), // Border value in case of a constant border.
         )
@@ -314,37 +634,150 @@ impl MotionDetector {
         )
         .expect("find_contours failed");
 
-        // Count contours in the processed frame.
-        Ok(match contours.is_empty() {
-            // No motion was detected.
-            true => None,
-            // Motion was found, return original video frame.
-            false => Some(frame),
-        })
+        // Scale factors from analysis frame to original frame coordinates.
+        let scale_x = f64::from(frame.frame.cols()) / f64::from(frame_one.cols());
+        let scale_y = f64::from(frame.frame.rows()) / f64::from(frame_one.rows());
+
+        // Discard contours smaller than the minimum area and sum up the remaining ones.
+        let mut boxes = Vec::new();
+        let mut changed_area = 0.;
+        for contour in contours.iter() {
+            let area = contour_area(&contour, false).expect("contour_area failed");
+            if area >= self.settings.min_contour_area {
+                changed_area += area;
+                let rect = bounding_rect(&contour).expect("bounding_rect failed");
+                boxes.push(BoundingBox::scaled(rect, scale_x, scale_y));
+            }
+        }
+        // Same area as the lighting change ratio: the motion detection zones (whole frame if none).
+        let score = (changed_area / self.active_area).min(1.);
+
+        // Count qualifying contours in the processed frame.
+        Ok(
+            match !boxes.is_empty()
+                && boxes.len() >= self.settings.min_contours.into()
+                && score >= self.settings.min_changed_ratio
+            {
+                // Motion was found, return the detection details.
+                true => {
+                    self.stats.detections += 1;
+                    Some(Detection {
+                        datetime: frame.datetime,
+                        contours: boxes.len(),
+                        boxes,
+                        area: changed_area * scale_x * scale_y,
+                        score,
+                        zones: self.touched_zones(&frame_one),
+                    })
+                }
+                // No motion was detected.
+                false => None,
+            },
+        )
+    }
+
+    /// Return the names of the include zones overlapping the motion in the given binary image.
+    fn touched_zones(&self, motion: &Mat) -> Vec<String> {
+        let mut overlap = Mat::default();
+        self.zone_masks
+            .iter()
+            .filter(|(_, mask)| {
+                bitwise_and(motion, mask, &mut overlap, &Mat::default())
+                    .expect("bitwise_and failed");
+                count_non_zero(&overlap).expect("count_non_zero failed") > 0
+            })
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Record a lighting event: consecutive lighting-changed frames count as a single event.
+    fn lighting_event(&mut self) {
+        if !self.lighting {
+            self.lighting = true;
+            self.stats.lighting_events += 1;
+        }
+    }
+
+    /// Return motion detection statistics.
+    pub fn stats(&self) -> DetectorStats {
+        self.stats
+    }
+
+    /// Write a PNG preview of the motion detection zones over the given frame.
+    pub fn write_mask_preview(&self, frame: &Frame, path: &Path) -> Result<(), ErrorKind> {
+        zone::write_preview(&self.settings.zones, &frame.frame, path)
     }
 }
 
-/// Video frame writer.
+/// Recorded video clip.
 ///
 /// # Fields
-/// * writer: OpenCV
+/// * writer: OpenCV VideoWriter instance
+/// * path: clip file path
+/// * start: DateTime of the first clip frame
+/// * last_motion: DateTime of the last frame where motion was detected
+/// * motion: whether motion was detected in the clip
+/// * metadata: clip metadata, written as JSON sidecar on finalization
+struct Clip {
+    writer: VideoWriter,
+    path: PathBuf,
+    start: DateTime<Local>,
+    last_motion: DateTime<Local>,
+    motion: bool,
+    metadata: ClipMetadata,
+}
+
+/// Video frame writer: records a clip for each motion event or, in continuous mode, every frame
+/// in fixed duration segments.
+///
+/// # Fields
+/// * video_path: output video file path, formatted with the clip start date&time
+/// * source: capture camera index or input video file, reported in the clip metadata
+/// * codec: video codec
+/// * fourcc: video codec fourcc code
+/// * fps: video framerate
+/// * size: video frame size
 /// * overlay: date&time video overlay
-/// * quiet: mute standard output
+/// * overlay_border: date&time video overlay border
+/// * mode: recording mode
+/// * pre_roll: number of frames recorded before the motion starts
+/// * post_roll: recording duration after the last motion
+/// * segment_duration: continuous recording segment duration
+/// * segment_max_size: continuous recording segment maximum size in bytes (0 for no limit)
+/// * buffer: ring buffer of the latest frames, used as pre-roll of the next clip
+/// * clip: clip being recorded
+/// * retention: recordings retention manager, enforced before opening each clip
 pub struct Writer {
-    writer: VideoWriter,
+    video_path: String,
+    source: String,
+    codec: Codec,
+    fourcc: i32,
+    fps: f64,
+    size: Size,
     overlay: bool,
     overlay_border: u8,
+    mode: RecordingMode,
+    pre_roll: usize,
+    post_roll: chrono::Duration,
+    segment_duration: chrono::Duration,
+    segment_max_size: u64,
+    buffer: VecDeque<Frame>,
+    clip: Option<Clip>,
+    retention: Option<Retention>,
 }
 
 impl Writer {
     /// Create an instance of the writer.
     ///
     /// # Parameters
-    /// * size: video frame
+    /// * video_path: output video file path (see
+    ///   <https://docs.rs/chrono/latest/chrono/format/strftime/index.html> for valid specifiers)
+    /// * codec: video codec
     /// * fps: video framerate
-    /// * video_path: output video file path
+    /// * size: video frame size
     /// * overlay: date and time video overlay
-    /// * quiet: mute stdout output
+    /// * overlay_border: date and time video overlay border
+    /// * recording: recording mode, pre-roll/post-roll and segment settings
     pub fn new(
         video_path: &str,
         codec: Codec,
@@ -352,20 +785,203 @@ impl Writer {
         size: Size,
         overlay: bool,
         overlay_border: u8,
+        recording: &RecordingSettings,
     ) -> Result<Self, ErrorKind> {
+        Ok(Self {
+            video_path: video_path.to_string(),
+            source: String::new(),
+            codec,
+            fourcc: codec.fourcc(),
+            fps,
+            size,
+            overlay,
+            overlay_border,
+            mode: recording.mode,
+            pre_roll: (f64::from(recording.pre_roll) * fps).ceil() as usize,
+            post_roll: chrono::Duration::seconds(recording.post_roll.into()),
+            segment_duration: chrono::Duration::minutes(recording.segment_minutes.into()),
+            segment_max_size: recording.segment_max_size * 1024 * 1024,
+            buffer: VecDeque::new(),
+            clip: None,
+            retention: None,
+        })
+    }
+
+    /// Report the given capture source (camera index or input video file) in the clip metadata.
+    pub fn with_source(mut self, source: &str) -> Self {
+        self.source = source.to_string();
+        self
+    }
+
+    /// Enforce the given retention policy before opening each clip: the oldest recordings are
+    /// deleted as needed and no clip is started while the disk is critically full.
+    pub fn with_retention(mut self, retention: Retention) -> Self {
+        self.retention = Some(retention);
+        self
+    }
+
+    /// Write passed frame according to the recording mode.
+    ///
+    /// Returns the path of the clip finalized by this frame, if any.
+    pub fn write(
+        &mut self,
+        frame: Frame,
+        detection: Option<Detection>,
+    ) -> Result<Option<PathBuf>, ErrorKind> {
+        match self.mode {
+            RecordingMode::Motion => self.write_motion(frame, detection),
+            RecordingMode::Continuous => self.write_continuous(frame, detection),
+        }
+    }
+
+    /// Motion recording: a clip is recorded from `pre_roll` before the first frame where motion
+    /// is detected until `post_roll` after the last one.
+    fn write_motion(
+        &mut self,
+        frame: Frame,
+        detection: Option<Detection>,
+    ) -> Result<Option<PathBuf>, ErrorKind> {
+        // Motion started: open a new clip, seeded with the pre-roll frames.
+        if detection.is_some() && self.clip.is_none() {
+            let start = self.buffer.front().map_or(frame.datetime, |f| f.datetime);
+            self.open(start, frame.datetime)?;
+            while let Some(buffered) = self.buffer.pop_front() {
+                self.record(buffered, None)?;
+            }
+        }
+
+        // No motion event in progress: keep the latest frames for the next clip pre-roll.
+        let Some(clip) = &mut self.clip else {
+            self.buffer.push_back(frame);
+            while self.buffer.len() > self.pre_roll {
+                self.buffer.pop_front();
+            }
+            return Ok(None);
+        };
+
+        if detection.is_some() {
+            clip.last_motion = frame.datetime;
+        }
+        let expired = frame.datetime - clip.last_motion > self.post_roll;
+
+        self.record(frame, detection.as_ref())?;
+
+        // Post-roll elapsed since the last motion: motion event is over.
+        if expired {
+            return self.finalize();
+        }
+
+        Ok(None)
+    }
+
+    /// Continuous recording: every frame is recorded, rolling to a new segment once the segment
+    /// duration or size limit is reached.
+    fn write_continuous(
+        &mut self,
+        frame: Frame,
+        detection: Option<Detection>,
+    ) -> Result<Option<PathBuf>, ErrorKind> {
+        // Segment is full: finalize it, the frame goes to a new segment.
+        let full = self.clip.as_ref().is_some_and(|clip| {
+            frame.datetime - clip.start >= self.segment_duration
+                || (self.segment_max_size > 0
+                    && fs::metadata(&clip.path).map_or(0, |meta| meta.len())
+                        >= self.segment_max_size)
+        });
+        let finalized = if full { self.finalize()? } else { None };
+
+        if self.clip.is_none() {
+            self.open(frame.datetime, frame.datetime)?;
+        }
+        if detection.is_some() {
+            if let Some(clip) = &mut self.clip {
+                clip.motion = true;
+                clip.last_motion = frame.datetime;
+            }
+        }
+
+        self.record(frame, detection.as_ref())?;
+
+        Ok(finalized)
+    }
+
+    /// Pause the recording: finalize the clip being recorded, if any, returning its path, and
+    /// drop the pre-roll frames so that the next clip doesn't start before the pause.
+    pub fn pause(&mut self) -> Result<Option<PathBuf>, ErrorKind> {
+        self.buffer.clear();
+        self.finalize()
+    }
+
+    /// Finalize the clip being recorded, if any, returning its path.
+    ///
+    /// In continuous mode, segments where motion was detected are flagged with a `-motion` file
+    /// name suffix. The clip metadata is written to a `.json` sidecar next to it, best effort:
+    /// the clip is returned even if its sidecar can't be written.
+    pub fn finalize(&mut self) -> Result<Option<PathBuf>, ErrorKind> {
+        let Some(mut clip) = self.clip.take() else {
+            return Ok(None);
+        };
+        if clip.writer.release().is_err() {
+            return Err(ErrorKind::InvalidOutput);
+        }
+
+        if self.mode == RecordingMode::Continuous && clip.motion {
+            let flagged = unique_path(with_suffix(&clip.path, "-motion"));
+            if fs::rename(&clip.path, &flagged).is_ok() {
+                clip.path = flagged;
+            }
+        }
+
+        let _ = clip.metadata.write(&clip.path);
+
+        Ok(Some(clip.path))
+    }
+
+    /// Open a new clip named after its start date&time (see `unique_path`).
+    fn open(
+        &mut self,
+        start: DateTime<Local>,
+        last_motion: DateTime<Local>,
+    ) -> Result<(), ErrorKind> {
+        // Make room for the new clip, refusing to start it if the disk is critically full.
+        if let Some(retention) = &mut self.retention {
+            retention.enforce()?;
+        }
+
+        let path = unique_path(PathBuf::from(start.format(&self.video_path).to_string()));
+
         // Construct the VideoWriter object.
-        match VideoWriter::new(video_path, codec.fourcc(), fps, size, true) {
-            Ok(writer) => Ok(Self {
-                writer,
-                overlay,
-                overlay_border,
-            }),
-            Err(_) => Err(ErrorKind::InvalidOutput),
+        match VideoWriter::new(
+            path.to_str().expect("invalid UTF-8 video path"),
+            self.fourcc,
+            self.fps,
+            self.size,
+            true,
+        ) {
+            Ok(writer) if writer.is_opened().unwrap_or(false) => {
+                self.clip = Some(Clip {
+                    writer,
+                    path,
+                    start,
+                    last_motion,
+                    motion: self.mode == RecordingMode::Motion,
+                    metadata: ClipMetadata::new(
+                        start,
+                        &self.source,
+                        self.size.width,
+                        self.size.height,
+                        self.fps,
+                        self.codec,
+                    ),
+                });
+                Ok(())
+            }
+            _ => Err(ErrorKind::InvalidOutput),
         }
     }
 
-    /// Write passed frame to the video file.
-    pub fn write(&mut self, mut frame: Frame) -> Result<(), ErrorKind> {
+    /// Write passed frame to the clip being recorded, along with its motion detection result.
+    fn record(&mut self, mut frame: Frame, detection: Option<&Detection>) -> Result<(), ErrorKind> {
         // Add date&time overlay.
         if self.overlay {
             // Text border.
@@ -404,20 +1020,46 @@ impl Writer {
         }
 
         // Write frame to video file.
-        if self.writer.write(&frame.frame).is_err() {
-            return Err(ErrorKind::FrameDropped);
+        if let Some(clip) = &mut self.clip {
+            if clip.writer.write(&frame.frame).is_err() {
+                return Err(ErrorKind::FrameDropped);
+            }
+            clip.metadata.push(frame.datetime, detection);
         }
 
         Ok(())
     }
 }
 
-/// Implement Drop trait for the Writer struct to release the VideoWriter on Writer drop.
+/// Return the path with the given suffix appended to the file name, before the extension.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+/// Return the path, with a `-<n>` file name suffix if a file already exists there: clips started
+/// within the same second (or segments rolled within the same second) never overwrite each
+/// other.
+fn unique_path(path: PathBuf) -> PathBuf {
+    if !path.exists() {
+        return path;
+    }
+    (1..)
+        .map(|n| with_suffix(&path, &format!("-{n}")))
+        .find(|path| !path.exists())
+        .expect("no free clip path")
+}
+
+/// Implement Drop trait for the Writer struct to finalize the clip being recorded on Writer drop.
 impl Drop for Writer {
     fn drop(&mut self) {
-        self.writer
-            .release()
-            .expect("unable to release VideoWriter");
+        // Nothing left to report the error to: never panic while dropping.
+        let _ = self.finalize();
     }
 }
 
@@ -473,9 +1115,11 @@ impl VideoStreamer {
 
 }
 
-/// Messanger
-///
+/// Alert backend: renders and delivers backend-neutral alerts.
 pub trait Messenger {
-    fn send(&mut self, payload: Payload) -> Result<(), ErrorKind>;
-    fn payload(&self, text: String) -> Result<Payload, ErrorKind>;
+    /// Backend name, recorded with each delivery in the event database.
+    fn name(&self) -> &str;
+
+    /// Render and deliver the alert. Backends unable to carry files drop the attachments.
+    fn send(&mut self, alert: &Alert) -> Result<(), ErrorKind>;
 }
@@ -18,30 +18,51 @@
 #[cfg(test)]
 mod test;
 
-use rustymode::{args::{Args, Parser}, color::{Colorizer, MsgType}, config::Config, Codec, Grabber, MotionDetector, Writer, VideoStreamer, Messenger, slack, Frame};
-use chrono::Local;
+use rustymode::{alert::{Alert, Attachment, Dispatcher, Severity}, args::{Args, Command, EventsArgs, Parser}, color::{Colorizer, MsgType}, config::{AlertSettings, Config, RecordingMode, SnapshotSettings}, error::ErrorKind, events::{EventChange, EventDb, EventFilter, EventTracker, MotionEvent}, hooks::{Hook, HookEvent, HookRunner}, notifier::Notifier, retention::Retention, schedule::{self, ArmState, Arming}, snapshot::{self, Snapshotter}, discord, email, matrix, mqtt::MqttPublisher, telegram, webhook, Grabber, MotionDetector, Writer, VideoStreamer, Detection, Frame};
+#[cfg(feature = "slack")]
+use rustymode::slack;
+use chrono::{DateTime, Local};
 use signal_hook::{consts::SIGINT, flag::register};
 use std::io;
 use std::{
-    path::Path,
+    fs, mem,
+    path::{Path, PathBuf},
     process,
     sync::{
         atomic::{AtomicBool, Ordering},
-        mpsc, Arc,
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc,
     },
     thread,
 };
 use std::io::Write;
 use std::net::TcpListener;
 use std::os::unix::raw::time_t;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::Duration;
 use opencv::core::{Mat, Vector};
 use opencv::imgcodecs;
 use opencv::videoio::{CAP_ANY, VideoCapture, VideoCaptureTrait};
 
+/// Notifications sent to the messenger thread.
+enum Notification {
+    /// A motion event started, with the motion detected in the frame.
+    Started {
+        event: MotionEvent,
+        frame: Frame,
+        detection: Detection,
+    },
+    /// A motion event ended.
+    Ended(MotionEvent),
+    /// A clip has been finalized, with the DateTime of its last frame.
+    ClipSaved(PathBuf, DateTime<Local>),
+    /// Output disk is critically full: recording is suspended.
+    DiskFull(String),
+}
+
 fn main() -> io::Result<()> {
     // Parse CLI arguments.
-    let args = Args::parse();
+    let mut args = Args::parse();
+    let command = args.command.take();
     // Parse config file and override options with CLI arguments.
     let config = match Config::parse() {
         Ok(config) => config,
@@ -59,17 +80,23 @@ fn main() -> io::Result<()> {
     }
     .override_with_args(args);
 
-    // Format video file path as <config.directory/date&time>.
-    let filename = Local::now()
-        .format(
-            config
-                .directory
-                // Output video file name (derived by file format) + extension.
-                .join(Path::new(&config.format).with_extension("mkv"))
-                // Convert Path object to string.
-                .to_str()
-                .unwrap(),
-        )
+    // Run the requested subcommand instead of recording.
+    match command {
+        Some(Command::Events(query)) => return events(&config, query),
+        Some(Command::Arm) => return arm(&config, ArmState::Armed),
+        Some(Command::Disarm) => return arm(&config, ArmState::Disarmed),
+        Some(Command::Auto) => return arm(&config, ArmState::Auto),
+        None => (),
+    }
+
+    // Video file path as <config.directory/date&time>: each clip is named after its start.
+    let video_path = config
+        .directory
+        // Output video file name (derived by file format) + extension.
+        .join(Path::new(&config.format).with_extension(config.recording.container.extension()))
+        // Convert Path object to string.
+        .to_str()
+        .unwrap()
         .to_string();
 
     // Instance of the frame grabber.
@@ -84,7 +111,7 @@ fn main() -> io::Result<()> {
             config.framerate.into(),
         ),
     };
-    let grabber = match grabber {
+    let mut grabber = match grabber {
         Ok(grabber) => grabber,
         Err(e) => {
             Colorizer::new(MsgType::Error, config.no_color, "error", e).print()?;
@@ -92,26 +119,46 @@ fn main() -> io::Result<()> {
         }
     };
 
+    // Capture source: input video file or camera index.
+    let source = if let Some(video) = &config.video {
+        video.display().to_string()
+    } else {
+        //format!("/dev/video{}", &config.index)
+        format!("{}", &config.index)
+    };
+
     // Print info.
     if !config.quiet {
         let mut colorizer = Colorizer::empty(MsgType::Info, config.no_color);
 
-        let input = if let Some(video) = &config.video {
-            video.display().to_string()
-        } else {
-            //format!("/dev/video{}", &config.index)
-            format!("{}", &config.index)
-        };
-
         let messages = vec![
-            ("==> Input", input),
+            ("==> Input", source.clone()),
             ("==> Framerate", grabber.get_fps().to_string()),
             (
                 "==> Frame size",
                 format!("{}x{}", grabber.get_width(), grabber.get_height()),
             ),
             ("==> Printing overlay", format!("{}", config.overlay)),
-            ("==> Output video file", filename.clone()),
+            ("==> Output directory", config.directory.display().to_string()),
+            ("==> Recording mode", format!("{:?}", config.recording.mode)),
+            (
+                "==> Codec",
+                format!(
+                    "{:?} (.{})",
+                    config.recording.codec,
+                    config.recording.container.extension()
+                ),
+            ),
+            match config.recording.mode {
+                RecordingMode::Motion => (
+                    "==> Pre/post-roll",
+                    format!("{}s/{}s", config.recording.pre_roll, config.recording.post_roll),
+                ),
+                RecordingMode::Continuous => (
+                    "==> Segment duration",
+                    format!("{}min", config.recording.segment_minutes),
+                ),
+            },
         ];
 
         for msg in messages {
@@ -121,18 +168,71 @@ fn main() -> io::Result<()> {
     }
 
     // Instance of the motion detector.
-    let detector = MotionDetector::new();
+    let detector = match MotionDetector::new(config.detector.clone()) {
+        Ok(detector) => detector,
+        Err(e) => {
+            Colorizer::new(MsgType::Error, config.no_color, "error", e).print()?;
+            process::exit(1);
+        }
+    };
+
+    // Write the motion detection zones preview over a captured frame and exit.
+    if let Some(mask_preview) = &config.mask_preview {
+        let result = grabber
+            .grab()
+            .and_then(|frame| detector.write_mask_preview(&frame, mask_preview));
+        if let Err(e) = result {
+            Colorizer::new(MsgType::Error, config.no_color, "error", e).print()?;
+            process::exit(1);
+        }
+        if !config.quiet {
+            Colorizer::new(
+                MsgType::Info,
+                config.no_color,
+                "==> Mask preview",
+                mask_preview.display(),
+            )
+            .print()?;
+        }
+        return Ok(());
+    }
+
+    // Check the output codec/container pair before recording anything.
+    if let Err(e) = config.recording.codec.validate(
+        config.recording.container,
+        &config.directory,
+        grabber.get_fps(),
+        grabber.get_size(),
+    ) {
+        Colorizer::new(MsgType::Error, config.no_color, "error", e).print()?;
+        process::exit(1);
+    }
+
+    // Apply the retention policy to the existing recordings, and to the event clips & snapshots.
+    let mut retention = Retention::new(&config.directory, &config.retention);
+    if let Ok(events) = EventDb::open(&config.directory) {
+        retention = retention.with_events(events);
+    }
+    match retention.enforce() {
+        Ok(removed) if !config.quiet && !removed.is_empty() => {
+            Colorizer::new(MsgType::Info, config.no_color, "==> Recordings removed", removed.len())
+                .print()?
+        }
+        Ok(_) => (),
+        Err(e) => Colorizer::new(MsgType::Warn, config.no_color, "warning", e).print()?,
+    }
 
     // Instance of the frame writer.
     let writer = match Writer::new(
-        &filename,
-        Codec::XVID,
+        &video_path,
+        config.recording.codec,
         grabber.get_fps(),
         grabber.get_size(),
         config.overlay,
         config.overlay_border,
+        &config.recording,
     ) {
-        Ok(writer) => writer,
+        Ok(writer) => writer.with_source(&source).with_retention(retention),
         Err(e) => {
             Colorizer::new(MsgType::Error, config.no_color, "error", e).print()?;
             process::exit(1);
@@ -155,23 +255,112 @@ fn main() -> io::Result<()> {
         }
     };
 
-    let messenger = match slack::new(
-        config.slack_url.as_str(),
-        config.slack_channel.as_str(),
-        config.slack_user.as_str(),
-    ) {
-        Ok(messenger) => messenger,
+    // Alert messengers, all receiving every alert.
+    #[cfg(not(feature = "slack"))]
+    if config.alerts.slack.is_some() {
+        Colorizer::new(
+            MsgType::Warn,
+            config.no_color,
+            "warning",
+            "Slack alerts are configured, but rustymode was built without the `slack` feature",
+        )
+        .print()?;
+    }
+    let dispatcher = match dispatcher(&config.alerts) {
+        Ok(dispatcher) => dispatcher,
         Err(e) => {
-        Colorizer::new(MsgType::Error, config.no_color, "error", e).print()?;
-        process::exit(1);
+            Colorizer::new(MsgType::Error, config.no_color, "error", e).print()?;
+            process::exit(1);
+        }
+    };
+    if !config.quiet {
+        let names = match dispatcher.is_empty() {
+            true => "disabled".to_string(),
+            false => dispatcher.names().join(", "),
+        };
+        Colorizer::new(MsgType::Info, config.no_color, "==> Alerts", names).print()?;
     }
+
+    // Save memory dropping `video_path`.
+    drop(video_path);
+
+    // Event snapshots writer.
+    let snapshotter = if config.snapshot.enabled {
+        match Snapshotter::new(
+            &config.snapshot_directory(),
+            &config.format,
+            config.snapshot.draw_boxes,
+            config.snapshot.quality,
+        ) {
+            Ok(snapshotter) => Some(snapshotter),
+            Err(e) => {
+                Colorizer::new(MsgType::Error, config.no_color, "error", e).print()?;
+                process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+
+    // Motion events tracker, recorded in the event database.
+    let camera = config.camera_name();
+    let tracker = EventTracker::new(
+        &camera,
+        chrono::Duration::seconds(config.recording.post_roll.into()),
+    );
+
+    // MQTT publisher of the motion events.
+    let mqtt = match config.mqtt.as_ref().map(|mqtt| MqttPublisher::connect(mqtt, &camera)) {
+        Some(Ok(mqtt)) => {
+            if !config.quiet {
+                Colorizer::new(MsgType::Info, config.no_color, "==> MQTT", &mqtt.topics().motion)
+                    .print()?;
+            }
+            Some(mqtt)
+        }
+        Some(Err(e)) => {
+            Colorizer::new(MsgType::Error, config.no_color, "error", e).print()?;
+            process::exit(1);
+        }
+        None => None,
+    };
+
+    // Shell hooks, reporting failures of the hooks running in the background.
+    let no_color = config.no_color;
+    let hooks = match HookRunner::new(&config.hooks, move |e| {
+        let _ = Colorizer::new(MsgType::Warn, no_color, "warning [hooks]", e).print();
+    }) {
+        Ok(hooks) => hooks,
+        Err(e) => {
+            Colorizer::new(MsgType::Error, config.no_color, "error", e).print()?;
+            process::exit(1);
+        }
     };
+    if !config.quiet && !hooks.names().is_empty() {
+        Colorizer::new(MsgType::Info, config.no_color, "==> Hooks", hooks.names().join(", "))
+            .print()?;
+    }
 
-    // Save memory dropping `filename`.
-    drop(filename);
+    // Motion alerts cooldown & digests.
+    let notifier = Notifier::new(
+        &camera,
+        chrono::Duration::seconds(config.alerts.cooldown.into()),
+        (config.alerts.digest_minutes > 0)
+            .then(|| chrono::Duration::minutes(config.alerts.digest_minutes.into())),
+        Local::now(),
+    );
+
+    // Alerting & recording schedules, with the manual arming state of the alerts.
+    let arming = match Arming::new(&config.schedule, &camera, &config.directory) {
+        Ok(arming) => arming,
+        Err(e) => {
+            Colorizer::new(MsgType::Error, config.no_color, "error [schedule]", e).print()?;
+            process::exit(1);
+        }
+    };
 
     // Run the program.
-    run(grabber, detector, writer, streamer, Box::new(messenger) as Box<dyn Messenger + Send>, config.no_color)?;
+    run(grabber, detector, writer, streamer, dispatcher, notifier, arming, tracker, snapshotter, mqtt, hooks, config.snapshot.clone(), &config.directory, config.no_color, config.quiet)?;
 
     // Gracefully terminated execution.
     if !config.quiet {
@@ -181,21 +370,126 @@ fn main() -> io::Result<()> {
     Ok(())
 }
 
+/// Open the event database in `directory`: on failure warn and go on without it.
+fn open_events(directory: &Path, no_color: bool) -> io::Result<Option<EventDb>> {
+    match EventDb::open(directory) {
+        Ok(db) => Ok(Some(db)),
+        Err(e) => {
+            Colorizer::new(MsgType::Warn, no_color, "warning [events]", e).print()?;
+            Ok(None)
+        }
+    }
+}
+
+/// Run the `events` subcommand: print the events matching the query from the event database.
+fn events(config: &Config, query: EventsArgs) -> io::Result<()> {
+    let filter = EventFilter {
+        since: query.since,
+        until: query.until,
+        camera: query.camera,
+        min_score: query.min_score.unwrap_or_default(),
+    };
+    let records = match EventDb::open(&config.directory).and_then(|db| db.query(&filter)) {
+        Ok(records) => records,
+        Err(e) => {
+            Colorizer::new(MsgType::Error, config.no_color, "error [events]", e).print()?;
+            process::exit(1);
+        }
+    };
+
+    let mut stdout = io::stdout().lock();
+    if query.json {
+        serde_json::to_writer_pretty(&mut stdout, &records)?;
+        return writeln!(stdout);
+    }
+
+    writeln!(
+        stdout,
+        "{:>6}  {:<25}  {:>8}  {:<12}  {:>6}  {:<16}  {:<7}  CLIP",
+        "ID", "START", "DURATION", "CAMERA", "SCORE", "ZONES", "ALERTS"
+    )?;
+    for record in records {
+        let sent = record.alerts.iter().filter(|alert| alert.status == "sent").count();
+        writeln!(
+            stdout,
+            "{:>6}  {:<25}  {:>8}  {:<12}  {:>6.3}  {:<16}  {:<7}  {}",
+            record.id,
+            record.start,
+            record
+                .duration
+                .map_or_else(|| "-".to_string(), |duration| format!("{duration:.1}s")),
+            record.camera,
+            record.peak_score,
+            record.zones.join(","),
+            format!("{sent}/{}", record.alerts.len()),
+            record.clip.as_deref().unwrap_or("-"),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Run the `arm`, `disarm` & `auto` subcommands: set the manual arming state of the camera
+/// alerts, picked up by the running instance.
+fn arm(config: &Config, state: ArmState) -> io::Result<()> {
+    let camera = config.camera_name();
+    if let Err(e) = schedule::set_state(&config.directory, &camera, state) {
+        Colorizer::new(MsgType::Error, config.no_color, "error [schedule]", e).print()?;
+        process::exit(1);
+    }
+    if !config.quiet {
+        Colorizer::new(MsgType::Info, config.no_color, format!("==> {camera} alerts"), state)
+            .print()?;
+    }
+    Ok(())
+}
+
 /// Run `bombuscv`: spawn & join frame grabber, detector and writer threads.
+#[allow(clippy::too_many_arguments)]
 fn run(
     mut grabber: Grabber,
     mut detector: MotionDetector,
     mut writer: Writer,
     mut streamer: VideoStreamer,
-    mut messenger: Box<dyn Messenger + Send>,
+    mut dispatcher: Dispatcher,
+    mut notifier: Notifier,
+    arming: Arming,
+    mut tracker: EventTracker,
+    mut snapshotter: Option<Snapshotter>,
+    mqtt: Option<MqttPublisher>,
+    hooks: HookRunner,
+    snapshot_settings: SnapshotSettings,
+    directory: &Path,
     no_color: bool,
+    quiet: bool,
 ) -> io::Result<()> {
+    // Event database connections, one per recording thread.
+    let detector_events = open_events(directory, no_color)?;
+    let writer_events = detector_events
+        .as_ref()
+        .and_then(|_| EventDb::open(directory).ok());
+    let messenger_events = detector_events
+        .as_ref()
+        .and_then(|_| EventDb::open(directory).ok());
+    // Finalization of the previous clip: events started before this run are not attached.
+    let mut clip_since = Local::now();
+    let camera = tracker.camera().to_string();
+    let messenger_camera = camera.clone();
+    let detector_hooks = hooks.clone();
+    let writer_hooks = hooks.clone();
+    let mut detector_arming = arming.clone();
+    let writer_arming = arming;
+    // Motion events are only handed to the messenger thread if some messenger is enabled.
+    let alerting = !dispatcher.is_empty();
+
     // Create channels for message passing between threads.
     // NOTE: using mpsc::sync_channel (blocking) to avoid channel size
     // growing indefinitely, resulting in infinite memory usage.
     let (raw_tx, raw_rx) = mpsc::sync_channel(100);
     let (proc_tx, proc_rx) = mpsc::sync_channel(100);
-    let (dtr_tx, msgr_rx) = mpsc::sync_channel(100);
+    // NOTE: the detector never waits for the messenger: only motion event changes are sent to it,
+    // a few per event, so its channel is unbounded.
+    let (dtr_tx, msgr_rx) = mpsc::channel();
     let (streamer_tx, streamer_rx) = mpsc::sync_channel(100);
 
     let streaming_enabled = Arc::new(AtomicBool::new(false));
@@ -207,7 +501,9 @@ fn run(
     let term_streamer = Arc::clone(&term);
     let term_writer = Arc::clone(&term);
     let term_detector = Arc::clone(&term);
-    let term_messenger = Arc::clone(&term);
+
+    // Writer thread reports finalized clips & a critically full disk to the messenger thread.
+    let writer_tx = dtr_tx.clone();
 
     // Register signal hook for SIGINT events: in this case error is unrecoverable, so report
     // it to the user & exit process with code error code.
@@ -256,47 +552,128 @@ fn run(
     // Spawn motion detection thread:
     // this thread receives frames from the grabber thread, processes it and if motion is detected,
     // passes the frame to the frame writing thread.
-    //let mut message_last_sent = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-    let mut message_last_sent = Duration::from_secs(0);
     let detector_handle = thread::spawn(move || -> io::Result<()> {
+        // Whether the alerts are armed, and whether the motion event in progress is alerted.
+        let mut armed = true;
+        let mut event_alerted = false;
+
         // Loop over received frames from the frame grabber.
         for frame in raw_rx {
             if term_detector.load(Ordering::Relaxed) {
-                return Ok(());
+                break;
             }
-            match detector.detect_motion(frame) {
+            match detector.detect_motion(&frame) {
                 // Valid frame is received.
-                Ok(val) => {
-                    // Motion has been detected: send frame to the video writer.
-                    if let Some(frame) = val {
-                        if proc_tx.send(frame).is_err() {
-                            Colorizer::new(
-                                MsgType::Warn,
-                                no_color,
-                                "warning",
-                                "unable to send processed frame to video output",
-                            )
-                            .print()?;
-                        };
-                        // TODO: make it sending a frame with motion detected rather than just bool
-                        if dtr_tx.send(true).is_err() {
-                            let time_now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-                            if time_now - message_last_sent > Duration::from_secs(10) {
-                                message_last_sent = time_now;
-                                Colorizer::new(
-                                    MsgType::Warn,
-                                    no_color,
-                                    "warning",
-                                    "unable to send signal to messenger thread",
-                                )
-                                .print()?;
+                Ok(detection) => {
+                    // Follow the alerts schedule and manual arming state.
+                    if alerting && detector_arming.alerts_armed(frame.datetime) != armed {
+                        armed = !armed;
+                        if !quiet {
+                            let state = if armed { "armed" } else { "disarmed" };
+                            Colorizer::new(MsgType::Info, no_color, "==> Alerts", state).print()?;
+                        }
+                    }
+
+                    // Group detections into motion events, recorded in the event database.
+                    match tracker.update(frame.datetime, detection.as_ref()) {
+                        Some(EventChange::Started) => {
+                            if let (Some(db), Some(event)) =
+                                (&detector_events, tracker.current_mut())
+                            {
+                                match db.insert_event(event) {
+                                    Ok(id) => event.id = Some(id),
+                                    Err(e) => {
+                                        Colorizer::new(MsgType::Warn, no_color, "warning [events]", e)
+                                            .print()?
+                                    }
+                                }
+                            }
+                            if let Some(event) = tracker.current() {
+                                if let Some(mqtt) = &mqtt {
+                                    report_mqtt(mqtt.motion_started(event), no_color)?;
+                                }
+                                let hook_event = HookEvent::event(event, false);
+                                run_hook(&detector_hooks, Hook::EventStart, &hook_event, no_color)?;
+                            }
+                            // Motion events are alerted if the alerts are armed when they start.
+                            event_alerted = alerting && armed;
+                            if event_alerted
+                                && let (Some(event), Some(detection)) = (tracker.current(), &detection)
+                            {
+                                let notification = Notification::Started {
+                                    event: event.clone(),
+                                    frame: Frame { frame: frame.frame.clone(), datetime: frame.datetime },
+                                    detection: detection.clone(),
+                                };
+                                notify(&dtr_tx, notification, no_color)?;
                             }
-                        };
+                        }
+                        Some(EventChange::Ended(event)) => {
+                            let event = end_event(event, snapshotter.as_mut(), detector_events.as_ref(), no_color)?;
+                            if let Some(mqtt) = &mqtt {
+                                publish_ended(mqtt, &event, no_color)?;
+                            }
+                            let hook_event = HookEvent::event(&event, true);
+                            run_hook(&detector_hooks, Hook::EventEnd, &hook_event, no_color)?;
+                            if mem::take(&mut event_alerted) {
+                                notify(&dtr_tx, Notification::Ended(event), no_color)?;
+                            }
+                        }
+                        None => (),
                     }
+
+                    // Keep the highest scoring frame of the event for its snapshot.
+                    if let (Some(snapshotter), Some(detection)) = (&mut snapshotter, &detection) {
+                        snapshotter.update(&frame, detection);
+                    }
+
+                    // Send frame to the video writer: it records the frames around motion.
+                    if proc_tx.send((frame, detection)).is_err() {
+                        Colorizer::new(
+                            MsgType::Warn,
+                            no_color,
+                            "warning",
+                            "unable to send processed frame to video output",
+                        )
+                        .print()?;
+                    };
                 }
                 // Last captured frame was an empty frame: no more input is provided, interrupt the
                 // thread (break the loop).
-                Err(_) => break,
+                Err(ErrorKind::EmptyFrame) => break,
+                // Motion detection algorithm failed on this frame: report it and move on.
+                Err(e) => Colorizer::new(MsgType::Warn, no_color, "warning", e).print()?,
+            }
+        }
+
+        // Record the motion event still in progress.
+        if let Some(event) = tracker.finish() {
+            let event = end_event(event, snapshotter.as_mut(), detector_events.as_ref(), no_color)?;
+            if let Some(mqtt) = &mqtt {
+                publish_ended(mqtt, &event, no_color)?;
+            }
+            run_hook(&detector_hooks, Hook::EventEnd, &HookEvent::event(&event, true), no_color)?;
+            if event_alerted {
+                notify(&dtr_tx, Notification::Ended(event), no_color)?;
+            }
+        }
+        if let Some(mqtt) = mqtt {
+            report_mqtt(mqtt.close(), no_color)?;
+        }
+
+        // Print motion detection statistics.
+        if !quiet {
+            let stats = detector.stats();
+            let mut colorizer = Colorizer::empty(MsgType::Info, no_color);
+            let messages = vec![
+                ("==> Processed frames", stats.frames),
+                ("==> Motion frames", stats.detections),
+                ("==> Lighting events", stats.lighting_events),
+            ];
+
+            for msg in messages {
+                colorizer.update(msg.0, msg.1);
+                colorizer.print()?;
             }
         }
 
@@ -310,14 +687,81 @@ fn run(
         if term_writer.load(Ordering::Relaxed) {
             return Ok(());
         }
+        // Whether recording is suspended because the disk is critically full.
+        let mut disk_full = false;
+        // Whether the video is recorded, as scheduled.
+        let mut recording = true;
+
+        // Record a finalized clip in the event database, run its hook and report it.
+        let mut clip_saved = |clip: PathBuf, until: DateTime<Local>| -> io::Result<()> {
+            attach_clip(writer_events.as_ref(), &camera, &clip, clip_since, until, no_color)?;
+            clip_since = until;
+            let hook_event = HookEvent::clip(&camera, until, &clip);
+            run_hook(&writer_hooks, Hook::ClipFinalized, &hook_event, no_color)?;
+            if !quiet {
+                Colorizer::new(MsgType::Info, no_color, "==> Clip saved", clip.display()).print()?
+            }
+            if alerting {
+                let _ = writer_tx.send(Notification::ClipSaved(clip, until));
+            }
+            Ok(())
+        };
+
         // Loop over received frames from the motion detector.
-        for frame in proc_rx {
-            // Write processed frames (motion detected) to the video output.
-            if let Err(e) = writer.write(frame) {
-                Colorizer::new(MsgType::Warn, no_color, "warning", e).print()?;
+        for (frame, detection) in proc_rx {
+            let datetime = frame.datetime;
+
+            // Out of the recording schedule: finalize the clip being recorded, skip the frames.
+            if !writer_arming.recording(datetime) {
+                if recording {
+                    recording = false;
+                    if !quiet {
+                        Colorizer::new(MsgType::Info, no_color, "==> Recording", "paused (schedule)")
+                            .print()?;
+                    }
+                    match writer.pause() {
+                        Ok(Some(clip)) => clip_saved(clip, datetime)?,
+                        Ok(None) => (),
+                        Err(e) => Colorizer::new(MsgType::Warn, no_color, "warning", e).print()?,
+                    }
+                }
+                continue;
+            }
+            if !recording {
+                recording = true;
+                if !quiet {
+                    Colorizer::new(MsgType::Info, no_color, "==> Recording", "resumed (schedule)")
+                        .print()?;
+                }
+            }
+
+            // Write processed frames around motion events to the video output.
+            match writer.write(frame, detection) {
+                Ok(clip) => {
+                    disk_full = false;
+                    if let Some(clip) = clip {
+                        clip_saved(clip, datetime)?;
+                    }
+                }
+                // Report the full disk once, until recording resumes.
+                Err(ErrorKind::DiskFull(msg)) => {
+                    if !disk_full {
+                        disk_full = true;
+                        Colorizer::new(MsgType::Error, no_color, "error", &msg).print()?;
+                        let _ = writer_tx.send(Notification::DiskFull(msg));
+                    }
+                }
+                Err(e) => Colorizer::new(MsgType::Warn, no_color, "warning", e).print()?,
             };
         }
 
+        // Finalize the clip being recorded on termination.
+        match writer.finalize() {
+            Ok(Some(clip)) => clip_saved(clip, Local::now())?,
+            Ok(None) => (),
+            Err(e) => Colorizer::new(MsgType::Warn, no_color, "warning", e).print()?,
+        };
+
         Ok(())
     });
 
@@ -406,29 +850,69 @@ fn run(
     });
 
     // Spawn messenger thread:
-    // this thread receives a message from detector thread, if motion detected
+    // this thread receives the motion events from the detector thread and alerts them, applying
+    // the cooldown and batching them into digests.
     let messenger_handle = thread::spawn(move || -> io::Result<()> {
-        // Loop over received frames from the motion detector.
-        for detected in msgr_rx {
-            if term_messenger.load(Ordering::Relaxed) {
-                println!("Exit 0 from messenger thread");
-                return Ok(());
-            }
-            let time_now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-            let motion_detected_msg= Local::now().format("%Y-%m-%d_%H-%M-%S").to_string() + " Motion Detected";
-            if time_now - message_last_sent > Duration::from_secs(5) {
-                message_last_sent = time_now;
-                Colorizer::new(MsgType::Info, no_color, "==>", motion_detected_msg.clone()).print()?;
-                let payload = messenger.payload(motion_detected_msg.to_owned())
-                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-                let res = messenger.send(payload);
-                match res {
-                    Ok(()) => (),
-                    Err(x) => println!("ERR: {:?}",x)
+        loop {
+            // Wake up when the next pending alert is due.
+            let notification = match notifier.next_due(Local::now()) {
+                Some(timeout) => match msgr_rx.recv_timeout(timeout) {
+                    Ok(notification) => Some(notification),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                },
+                None => match msgr_rx.recv() {
+                    Ok(notification) => Some(notification),
+                    Err(_) => break,
+                },
+            };
+
+            let mut alerts = match notification {
+                Some(Notification::Started { event, mut frame, detection }) => {
+                    if !notifier.started(&event) {
+                        continue;
+                    }
+                    let mut alert = Alert::new("Motion started", Severity::Warning, frame.datetime, &messenger_camera)
+                        .with_body(&format!("score {:.3}, {} areas", detection.score, detection.contours))
+                        .with_event_id(event.id);
+
+                    // Attach the triggering frame as JPEG snapshot.
+                    if snapshot_settings.draw_boxes {
+                        if let Err(e) = snapshot::draw_boxes(&mut frame.frame, &detection.boxes) {
+                            Colorizer::new(MsgType::Warn, no_color, "warning", e).print()?;
+                        }
+                    }
+                    match snapshot::encode_jpeg(&frame.frame, snapshot_settings.quality) {
+                        Ok(jpeg) => alert = alert.with_attachment(Attachment::jpeg("motion.jpg", jpeg)),
+                        Err(e) => Colorizer::new(MsgType::Warn, no_color, "warning", e).print()?,
+                    }
+                    vec![alert.with_detection(detection)]
+                }
+                Some(Notification::Ended(event)) => notifier.ended(event, Local::now()),
+                Some(Notification::ClipSaved(clip, until)) => notifier.clip(&clip, until),
+                // Disk full alerts are never throttled: the writer only reports them once.
+                Some(Notification::DiskFull(msg)) => {
+                    let alert = Alert::new("Recording suspended", Severity::Critical, Local::now(), &messenger_camera)
+                        .with_body(&msg);
+                    dispatch(&mut dispatcher, &alert, messenger_events.as_ref(), no_color)?;
+                    continue;
                 }
+                None => Vec::new(),
+            };
+            alerts.extend(notifier.poll(Local::now()));
+
+            for alert in alerts {
+                Colorizer::new(MsgType::Info, no_color, "==>", alert.text()).print()?;
+                dispatch(&mut dispatcher, &alert, messenger_events.as_ref(), no_color)?;
             }
         }
 
+        // Send the alerts still pending on termination.
+        for alert in notifier.flush(Local::now()) {
+            Colorizer::new(MsgType::Info, no_color, "==>", alert.text()).print()?;
+            dispatch(&mut dispatcher, &alert, messenger_events.as_ref(), no_color)?;
+        }
+
         println!("Exit 0 from messenger thread");
         Ok(())
     });
@@ -440,5 +924,146 @@ fn run(
     streamer_handle.join().expect("cannot join streamer thread")?;
     messenger_handle.join().expect("cannot join messenger thread")?;
 
+    // Let the last hooks complete.
+    hooks.wait();
+
+    Ok(())
+}
+
+/// Create the alert messengers enabled in the `[alerts]` table.
+fn dispatcher(settings: &AlertSettings) -> Result<Dispatcher, ErrorKind> {
+    let mut dispatcher = Dispatcher::new();
+    if !settings.enabled {
+        return Ok(dispatcher);
+    }
+
+    #[cfg(feature = "slack")]
+    if let Some(slack) = &settings.slack {
+        dispatcher.push(Box::new(slack::from_settings(slack)?));
+    }
+    if let Some(webhook) = &settings.webhook {
+        dispatcher.push(Box::new(webhook::from_settings(webhook)?));
+    }
+
+    if let Some(telegram) = &settings.telegram {
+        dispatcher.push(Box::new(telegram::from_settings(telegram)?));
+    }
+
+    if let Some(discord) = &settings.discord {
+        dispatcher.push(Box::new(discord::from_settings(discord)?));
+    }
+
+    if let Some(matrix) = &settings.matrix {
+        dispatcher.push(Box::new(matrix::from_settings(matrix)?));
+    }
+
+    if let Some(email) = &settings.email {
+        dispatcher.push(Box::new(email::from_settings(email)?));
+    }
+
+    Ok(dispatcher)
+}
+
+/// Save the snapshot of an ended motion event and record it in the event database, returning
+/// the recorded event.
+fn end_event(
+    mut event: MotionEvent,
+    snapshotter: Option<&mut Snapshotter>,
+    events: Option<&EventDb>,
+    no_color: bool,
+) -> io::Result<MotionEvent> {
+    match snapshotter.map(|snapshotter| snapshotter.save(event.start)) {
+        Some(Ok(snapshot)) => event.snapshot = snapshot,
+        Some(Err(e)) => Colorizer::new(MsgType::Warn, no_color, "warning", e).print()?,
+        None => (),
+    }
+    if let Some(Err(e)) = events.map(|db| db.finish_event(&event)) {
+        Colorizer::new(MsgType::Warn, no_color, "warning [events]", e).print()?;
+    }
+    Ok(event)
+}
+
+/// Publish an ended motion event to MQTT, along with its snapshot.
+fn publish_ended(mqtt: &MqttPublisher, event: &MotionEvent, no_color: bool) -> io::Result<()> {
+    let snapshot = event.snapshot.as_ref().and_then(|path| fs::read(path).ok());
+    report_mqtt(mqtt.motion_ended(event, snapshot), no_color)
+}
+
+/// Hand a notification to the messenger thread, warning if it's gone.
+fn notify(tx: &Sender<Notification>, notification: Notification, no_color: bool) -> io::Result<()> {
+    if tx.send(notification).is_err() {
+        Colorizer::new(
+            MsgType::Warn,
+            no_color,
+            "warning",
+            "unable to send signal to messenger thread",
+        )
+        .print()?;
+    }
+    Ok(())
+}
+
+/// Start a shell hook in the background, warning if it can't be started.
+fn run_hook(hooks: &HookRunner, hook: Hook, event: &HookEvent, no_color: bool) -> io::Result<()> {
+    if let Err(e) = hooks.run(hook, event) {
+        Colorizer::new(MsgType::Warn, no_color, "warning [hooks]", e).print()?;
+    }
+    Ok(())
+}
+
+/// Warn about MQTT publishing errors.
+fn report_mqtt(result: Result<(), ErrorKind>, no_color: bool) -> io::Result<()> {
+    if let Err(e) = result {
+        Colorizer::new(MsgType::Warn, no_color, "warning [mqtt]", e).print()?;
+    }
+    Ok(())
+}
+
+/// Attach a finalized clip to the motion events started after `since` and up to `until`.
+fn attach_clip(
+    events: Option<&EventDb>,
+    camera: &str,
+    clip: &Path,
+    since: chrono::DateTime<Local>,
+    until: chrono::DateTime<Local>,
+    no_color: bool,
+) -> io::Result<()> {
+    if let Some(Err(e)) = events.map(|db| db.attach_clip(camera, clip, since, until)) {
+        Colorizer::new(MsgType::Warn, no_color, "warning [events]", e).print()?;
+    }
+    Ok(())
+}
+
+/// Deliver an alert to every messenger, reporting and recording each delivery.
+fn dispatch(
+    dispatcher: &mut Dispatcher,
+    alert: &Alert,
+    events: Option<&EventDb>,
+    no_color: bool,
+) -> io::Result<()> {
+    for delivery in dispatcher.dispatch(alert) {
+        record_alert(events, alert.event_id, &delivery.backend, &delivery.result, no_color)?;
+        if let Err(e) = delivery.result {
+            Colorizer::new(MsgType::Warn, no_color, format!("warning [{}]", delivery.backend), e)
+                .print()?;
+        }
+    }
+    Ok(())
+}
+
+/// Record an alert delivery attempt in the event database.
+fn record_alert(
+    events: Option<&EventDb>,
+    event_id: Option<i64>,
+    backend: &str,
+    result: &Result<(), ErrorKind>,
+    no_color: bool,
+) -> io::Result<()> {
+    let error = result.as_ref().err().map(ToString::to_string);
+    if let Some(Err(e)) =
+        events.map(|db| db.insert_alert(event_id, backend, Local::now(), error.as_deref()))
+    {
+        Colorizer::new(MsgType::Warn, no_color, "warning [events]", e).print()?;
+    }
     Ok(())
 }
@@ -0,0 +1,208 @@
+// rustymode: Fork of bombuscv, originally an OpenCV-based motion detection/recording software built for research on bumblebees.
+// Originally developed as bombuscv by Marco Radocchia (C) 2022
+// Modified and renamed to rustymode by Dmitry Sobolev (C) 2025
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use crate::{
+    alert::{Alert, Attachment},
+    error::ErrorKind,
+    Messenger,
+};
+use chrono::Local;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::time::Duration;
+use url::Url;
+
+/// Matrix alert settings (`[alerts.matrix]` table).
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MatrixSettings {
+    /// Homeserver base URL.
+    pub homeserver: String,
+
+    /// Access token of the user posting the alerts.
+    pub access_token: String,
+
+    /// Room id (`!room:server`) the alerts are posted to.
+    pub room_id: String,
+
+    /// Request timeout in seconds.
+    pub timeout: u16,
+}
+
+/// Implement the Default trait for MatrixSettings.
+impl Default for MatrixSettings {
+    /// Default Matrix settings.
+    fn default() -> Self {
+        Self {
+            homeserver: String::new(),
+            access_token: String::new(),
+            room_id: String::new(),
+            timeout: 30,
+        }
+    }
+}
+
+/// Client-server API response: only the fields used by the messenger.
+#[derive(Deserialize)]
+struct ApiResponse {
+    #[serde(default)]
+    content_uri: Option<String>,
+}
+
+/// Client-server API error response.
+#[derive(Deserialize)]
+struct ApiError {
+    #[serde(default)]
+    errcode: Option<String>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// Map HTTP client errors to Matrix send errors, describing Matrix error responses.
+fn http_err(e: ureq::Error) -> ErrorKind {
+    let msg = match e {
+        ureq::Error::Status(code, response) => match response.into_json::<ApiError>() {
+            Ok(ApiError { errcode: Some(errcode), error: Some(error) }) => {
+                format!("{errcode}: {error}")
+            }
+            Ok(ApiError { errcode: Some(errcode), error: None }) => errcode,
+            _ => format!("HTTP status {code}"),
+        },
+        e => e.to_string(),
+    };
+    ErrorKind::UnableToSendMatrixMessage(msg)
+}
+
+/// Matrix client-server API messenger: posts the alert text as `m.text` message, followed by the
+/// snapshot as `m.image` message.
+///
+/// # Fields
+/// * homeserver: homeserver base URL
+/// * access_token: access token of the user posting the alerts
+/// * room_id: room the alerts are posted to
+/// * transactions: number of events sent, making transaction ids unique
+/// * agent: HTTP client
+pub struct MatrixMessenger {
+    pub homeserver: Url,
+    pub access_token: String,
+    pub room_id: String,
+    transactions: u64,
+    agent: ureq::Agent,
+}
+
+/// Create the Matrix messenger described by the settings.
+pub fn from_settings(settings: &MatrixSettings) -> Result<MatrixMessenger, ErrorKind> {
+    let homeserver = Url::parse(&settings.homeserver)
+        .map_err(|e| ErrorKind::CreateMatrixClientErr(format!("invalid homeserver URL: {e}")))?;
+    if homeserver.cannot_be_a_base() {
+        return Err(ErrorKind::CreateMatrixClientErr(
+            "invalid homeserver URL".to_string(),
+        ));
+    }
+    if settings.access_token.is_empty() {
+        return Err(ErrorKind::CreateMatrixClientErr("missing access token".to_string()));
+    }
+    if !settings.room_id.starts_with('!') {
+        return Err(ErrorKind::CreateMatrixClientErr(format!(
+            "invalid room id '{}'",
+            settings.room_id
+        )));
+    }
+
+    Ok(MatrixMessenger {
+        homeserver,
+        access_token: settings.access_token.clone(),
+        room_id: settings.room_id.clone(),
+        transactions: 0,
+        agent: ureq::AgentBuilder::new()
+            .timeout(Duration::from_secs(settings.timeout.into()))
+            .build(),
+    })
+}
+
+impl MatrixMessenger {
+    /// Return the homeserver URL of the API endpoint, percent-encoding each path segment.
+    fn endpoint(&self, segments: &[&str]) -> Url {
+        let mut url = self.homeserver.clone();
+        url.path_segments_mut()
+            .expect("homeserver URL checked on creation")
+            .pop_if_empty()
+            .extend(segments);
+        url
+    }
+
+    /// Send a `m.room.message` event to the room.
+    fn send_message(&mut self, content: Value) -> Result<(), ErrorKind> {
+        self.transactions += 1;
+        let txn_id = format!(
+            "rustymode-{}-{}",
+            Local::now().timestamp_millis(),
+            self.transactions
+        );
+        let url = self.endpoint(&[
+            "_matrix", "client", "v3", "rooms", &self.room_id, "send", "m.room.message", &txn_id,
+        ]);
+
+        self.agent
+            .put(url.as_str())
+            .set("Authorization", &format!("Bearer {}", self.access_token))
+            .send_json(content)
+            .map_err(http_err)?;
+        Ok(())
+    }
+
+    /// Upload a file to the media repository, returning its `mxc://` URI.
+    fn upload(&self, attachment: &Attachment) -> Result<String, ErrorKind> {
+        let mut url = self.endpoint(&["_matrix", "media", "v3", "upload"]);
+        url.query_pairs_mut().append_pair("filename", &attachment.name);
+
+        self.agent
+            .post(url.as_str())
+            .set("Authorization", &format!("Bearer {}", self.access_token))
+            .set("Content-Type", &attachment.content_type)
+            .send_bytes(&attachment.data)
+            .map_err(http_err)?
+            .into_json::<ApiResponse>()
+            .map_err(|e| ErrorKind::UnableToSendMatrixMessage(e.to_string()))?
+            .content_uri
+            .ok_or_else(|| {
+                ErrorKind::UnableToSendMatrixMessage("missing content URI in upload response".to_string())
+            })
+    }
+}
+
+impl Messenger for MatrixMessenger {
+    fn name(&self) -> &str {
+        "matrix"
+    }
+
+    fn send(&mut self, alert: &Alert) -> Result<(), ErrorKind> {
+        self.send_message(json!({ "msgtype": "m.text", "body": alert.text() }))?;
+
+        if let Some(snapshot) = alert.snapshot() {
+            let content_uri = self.upload(snapshot)?;
+            self.send_message(json!({
+                "msgtype": "m.image",
+                "body": snapshot.name,
+                "url": content_uri,
+                "info": { "mimetype": snapshot.content_type, "size": snapshot.data.len() },
+            }))?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+// rustymode: Fork of bombuscv, originally an OpenCV-based motion detection/recording software built for research on bumblebees.
+// Originally developed as bombuscv by Marco Radocchia (C) 2022
+// Modified and renamed to rustymode by Dmitry Sobolev (C) 2025
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use crate::{error::ErrorKind, BoundingBox, Codec, Detection};
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Return the JSON sidecar path of a recording: same file name, `.json` extension.
+pub fn sidecar_path(recording: &Path) -> PathBuf {
+    recording.with_extension("json")
+}
+
+/// Motion detected in a recorded frame.
+///
+/// # Fields
+/// * index: frame index within the recording
+/// * datetime: frame capture date&time
+/// * score: ratio of the motion detection zones (whole frame if none) covered by motion (0-1)
+/// * boxes: motion bounding boxes, in frame coordinates
+#[derive(Serialize, Debug, Clone)]
+pub struct FrameMetadata {
+    pub index: u64,
+    pub datetime: String,
+    pub score: f64,
+    pub boxes: Vec<BoundingBox>,
+}
+
+/// Recording metadata, written as a JSON sidecar next to each finalized recording.
+///
+/// # Fields
+/// * start: first frame capture date&time
+/// * end: last frame capture date&time
+/// * source: capture camera index or input video file
+/// * width: frame width
+/// * height: frame height
+/// * fps: video framerate
+/// * codec: video codec
+/// * frame_count: number of recorded frames
+/// * motion: frames where motion was detected
+#[derive(Serialize, Debug, Clone)]
+pub struct ClipMetadata {
+    pub start: String,
+    pub end: String,
+    pub source: String,
+    pub width: i32,
+    pub height: i32,
+    pub fps: f64,
+    pub codec: Codec,
+    pub frame_count: u64,
+    pub motion: Vec<FrameMetadata>,
+}
+
+impl ClipMetadata {
+    /// Create the metadata of an empty recording.
+    pub fn new(
+        start: DateTime<Local>,
+        source: &str,
+        width: i32,
+        height: i32,
+        fps: f64,
+        codec: Codec,
+    ) -> Self {
+        Self {
+            start: start.to_rfc3339(),
+            end: start.to_rfc3339(),
+            source: source.to_string(),
+            width,
+            height,
+            fps,
+            codec,
+            frame_count: 0,
+            motion: Vec::new(),
+        }
+    }
+
+    /// Account for a recorded frame and its motion detection result.
+    pub fn push(&mut self, datetime: DateTime<Local>, detection: Option<&Detection>) {
+        if let Some(detection) = detection {
+            self.motion.push(FrameMetadata {
+                index: self.frame_count,
+                datetime: datetime.to_rfc3339(),
+                score: detection.score,
+                boxes: detection.boxes.clone(),
+            });
+        }
+        self.end = datetime.to_rfc3339();
+        self.frame_count += 1;
+    }
+
+    /// Write the JSON sidecar of the given recording.
+    pub fn write(&self, recording: &Path) -> Result<PathBuf, ErrorKind> {
+        let path = sidecar_path(recording);
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| ErrorKind::SidecarErr(e.to_string()))?;
+        fs::write(&path, json)
+            .map_err(|e| ErrorKind::SidecarErr(format!("{}: {e}", path.display())))?;
+
+        Ok(path)
+    }
+}
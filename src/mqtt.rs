@@ -0,0 +1,323 @@
+// rustymode: Fork of bombuscv, originally an OpenCV-based motion detection/recording software built for research on bumblebees.
+// Originally developed as bombuscv by Marco Radocchia (C) 2022
+// Modified and renamed to rustymode by Dmitry Sobolev (C) 2025
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use crate::{error::ErrorKind, events::MotionEvent};
+use rumqttc::{Client, Event, LastWill, MqttOptions, Outgoing, Packet, QoS};
+use serde::Deserialize;
+use serde_json::json;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Availability payload while rustymode is running.
+pub const ONLINE: &str = "online";
+
+/// Availability payload once rustymode is stopped (also the last will).
+pub const OFFLINE: &str = "offline";
+
+/// MQTT settings (`[mqtt]` table).
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MqttSettings {
+    /// Broker host.
+    pub host: String,
+
+    /// Broker port.
+    pub port: u16,
+
+    /// Client id (defaults to `rustymode-<camera>`).
+    pub client_id: String,
+
+    /// Broker username (empty for no authentication).
+    pub username: String,
+
+    /// Broker password.
+    pub password: String,
+
+    /// Prefix of the camera topics: `<topic_prefix>/<camera>/...`.
+    pub topic_prefix: String,
+
+    /// Publish Home Assistant MQTT discovery configs.
+    pub discovery: bool,
+
+    /// Home Assistant discovery topic prefix.
+    pub discovery_prefix: String,
+
+    /// Keep alive interval in seconds.
+    pub keep_alive: u16,
+}
+
+/// Implement the Default trait for MqttSettings.
+impl Default for MqttSettings {
+    /// Default MQTT settings.
+    fn default() -> Self {
+        Self {
+            host: String::from("localhost"),
+            port: 1883,
+            client_id: String::new(),
+            username: String::new(),
+            password: String::new(),
+            topic_prefix: String::from("rustymode"),
+            discovery: true,
+            discovery_prefix: String::from("homeassistant"),
+            keep_alive: 30,
+        }
+    }
+}
+
+/// Return the camera name as MQTT topic level / Home Assistant object id: lowercase ASCII
+/// alphanumerics, other characters replaced by `_`.
+pub fn object_id(camera: &str) -> String {
+    camera
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c.to_ascii_lowercase(),
+            false => '_',
+        })
+        .collect()
+}
+
+/// Camera topics.
+///
+/// # Fields
+/// * availability: `online`/`offline` (retained, last will)
+/// * motion: `ON`/`OFF` motion state (retained)
+/// * event: motion event JSON
+/// * snapshot: JPEG snapshot of the last motion event (retained)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Topics {
+    pub availability: String,
+    pub motion: String,
+    pub event: String,
+    pub snapshot: String,
+}
+
+impl Topics {
+    /// Return the topics of the camera under the prefix.
+    pub fn new(prefix: &str, camera: &str) -> Self {
+        let base = format!("{}/{}", prefix.trim_end_matches('/'), object_id(camera));
+        Self {
+            availability: format!("{base}/availability"),
+            motion: format!("{base}/motion"),
+            event: format!("{base}/event"),
+            snapshot: format!("{base}/snapshot"),
+        }
+    }
+}
+
+/// Return the Home Assistant discovery configs of the camera, as (topic, payload) pairs: a
+/// motion `binary_sensor` and a `camera` showing the last snapshot.
+pub fn discovery(prefix: &str, camera: &str, topics: &Topics) -> Vec<(String, String)> {
+    let id = format!("rustymode_{}", object_id(camera));
+    let prefix = prefix.trim_end_matches('/');
+    let device = json!({
+        "identifiers": [id],
+        "name": camera,
+        "manufacturer": "rustymode",
+        "model": "rustymode",
+        "sw_version": env!("CARGO_PKG_VERSION"),
+    });
+
+    let motion = json!({
+        "name": "Motion",
+        "unique_id": format!("{id}_motion"),
+        "device_class": "motion",
+        "state_topic": topics.motion,
+        "payload_on": "ON",
+        "payload_off": "OFF",
+        "json_attributes_topic": topics.event,
+        "availability_topic": topics.availability,
+        "payload_available": ONLINE,
+        "payload_not_available": OFFLINE,
+        "device": device,
+    });
+    let snapshot = json!({
+        "name": "Snapshot",
+        "unique_id": format!("{id}_snapshot"),
+        "topic": topics.snapshot,
+        "availability_topic": topics.availability,
+        "payload_available": ONLINE,
+        "payload_not_available": OFFLINE,
+        "device": device,
+    });
+
+    vec![
+        (format!("{prefix}/binary_sensor/{id}/motion/config"), motion.to_string()),
+        (format!("{prefix}/camera/{id}/snapshot/config"), snapshot.to_string()),
+    ]
+}
+
+/// Return the JSON payload of a motion event state change (`start` or `end`).
+pub fn event_payload(kind: &str, event: &MotionEvent) -> String {
+    json!({
+        "type": kind,
+        "id": event.id,
+        "camera": event.camera,
+        "start": event.start.to_rfc3339(),
+        "end": event.end.to_rfc3339(),
+        "duration": event.duration().num_milliseconds() as f64 / 1000.,
+        "peak_score": event.peak_score,
+        "zones": event.zones,
+        "snapshot": event.snapshot,
+    })
+    .to_string()
+}
+
+/// MQTT publisher: motion state, events and snapshots of a camera, with availability and Home
+/// Assistant discovery.
+///
+/// Publishing never blocks: messages are dropped if the broker can't keep up.
+///
+/// # Fields
+/// * client: MQTT client, driven by the connection thread
+/// * topics: camera topics
+/// * closing: set on close, stopping the connection thread
+/// * connection: connection thread handle
+pub struct MqttPublisher {
+    client: Client,
+    topics: Topics,
+    closing: Arc<AtomicBool>,
+    connection: Option<thread::JoinHandle<()>>,
+}
+
+impl MqttPublisher {
+    /// Connect to the broker. Availability and discovery configs are published again on every
+    /// (re)connection.
+    pub fn connect(settings: &MqttSettings, camera: &str) -> Result<Self, ErrorKind> {
+        if settings.host.is_empty() {
+            return Err(ErrorKind::MqttErr("missing broker host".to_string()));
+        }
+
+        let topics = Topics::new(&settings.topic_prefix, camera);
+        let client_id = match settings.client_id.is_empty() {
+            true => format!("rustymode-{}", object_id(camera)),
+            false => settings.client_id.clone(),
+        };
+        let mut options = MqttOptions::new(client_id, &settings.host, settings.port);
+        options
+            .set_keep_alive(Duration::from_secs(settings.keep_alive.into()))
+            .set_last_will(LastWill::new(
+                &topics.availability,
+                OFFLINE,
+                QoS::AtLeastOnce,
+                true,
+            ));
+        if !settings.username.is_empty() {
+            options.set_credentials(&settings.username, &settings.password);
+        }
+
+        let (client, mut connection) = Client::new(options, 64);
+        let closing = Arc::new(AtomicBool::new(false));
+
+        // Messages published on connection.
+        let mut on_connect = vec![(topics.availability.clone(), ONLINE.to_string())];
+        if settings.discovery {
+            on_connect.extend(discovery(&settings.discovery_prefix, camera, &topics));
+        }
+
+        let publisher = client.clone();
+        let stop = Arc::clone(&closing);
+        let handle = thread::spawn(move || {
+            for notification in connection.iter() {
+                match notification {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        for (topic, payload) in &on_connect {
+                            let _ = publisher.try_publish(
+                                topic,
+                                QoS::AtLeastOnce,
+                                true,
+                                payload.as_bytes(),
+                            );
+                        }
+                    }
+                    // Pending messages are sent before the disconnection.
+                    Ok(Event::Outgoing(Outgoing::Disconnect)) => break,
+                    Ok(_) => (),
+                    Err(_) if stop.load(Ordering::Relaxed) => break,
+                    // Broker unreachable: retry the connection.
+                    Err(_) => thread::sleep(Duration::from_secs(1)),
+                }
+            }
+        });
+
+        Ok(Self {
+            client,
+            topics,
+            closing,
+            connection: Some(handle),
+        })
+    }
+
+    /// Return the camera topics.
+    pub fn topics(&self) -> &Topics {
+        &self.topics
+    }
+
+    /// Publish the motion state and event of a started motion event.
+    pub fn motion_started(&self, event: &MotionEvent) -> Result<(), ErrorKind> {
+        self.publish(&self.topics.motion, true, "ON")?;
+        self.publish(&self.topics.event, false, event_payload("start", event))
+    }
+
+    /// Publish the motion state, event and snapshot of an ended motion event.
+    pub fn motion_ended(
+        &self,
+        event: &MotionEvent,
+        snapshot: Option<Vec<u8>>,
+    ) -> Result<(), ErrorKind> {
+        self.publish(&self.topics.motion, true, "OFF")?;
+        self.publish(&self.topics.event, false, event_payload("end", event))?;
+        match snapshot {
+            Some(snapshot) => self.publish(&self.topics.snapshot, true, snapshot),
+            None => Ok(()),
+        }
+    }
+
+    /// Publish `offline` availability and disconnect from the broker.
+    ///
+    /// Never blocks on an unreachable broker: if the disconnection can't be queued (request queue
+    /// full), the connection thread is left to exit on its own, at the next connection error.
+    pub fn close(mut self) -> Result<(), ErrorKind> {
+        let _ = self.publish(&self.topics.availability, true, OFFLINE);
+        self.closing.store(true, Ordering::Relaxed);
+        self.client
+            .try_disconnect()
+            .map_err(|e| ErrorKind::MqttErr(format!("unable to disconnect: {e}")))?;
+        if let Some(handle) = self.connection.take() {
+            let _ = handle.join();
+        }
+
+        Ok(())
+    }
+
+    /// Queue a message without blocking.
+    fn publish(
+        &self,
+        topic: &str,
+        retain: bool,
+        payload: impl Into<Vec<u8>>,
+    ) -> Result<(), ErrorKind> {
+        self.client
+            .try_publish(topic, QoS::AtLeastOnce, retain, payload)
+            .map_err(|e| ErrorKind::MqttErr(e.to_string()))
+    }
+}
@@ -0,0 +1,88 @@
+// rustymode: Fork of bombuscv, originally an OpenCV-based motion detection/recording software built for research on bumblebees.
+// Originally developed as bombuscv by Marco Radocchia (C) 2022
+// Modified and renamed to rustymode by Dmitry Sobolev (C) 2025
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Return a MIME multipart boundary unlikely to appear in the parts.
+pub fn boundary() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_nanos())
+        .unwrap_or_default();
+    format!("rustymode-{nanos:x}")
+}
+
+/// `multipart/form-data` request body, for the HTTP APIs uploading files.
+///
+/// # Fields
+/// * boundary: parts boundary
+/// * body: encoded parts
+pub struct Form {
+    boundary: String,
+    body: Vec<u8>,
+}
+
+impl Default for Form {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Form {
+    /// Create an empty form.
+    pub fn new() -> Self {
+        Self {
+            boundary: boundary(),
+            body: Vec::new(),
+        }
+    }
+
+    /// Add a text field.
+    pub fn text(mut self, name: &str, value: &str) -> Self {
+        self.part(&format!("Content-Disposition: form-data; name=\"{name}\"\r\n"));
+        self.body.extend_from_slice(value.as_bytes());
+        self.body.extend_from_slice(b"\r\n");
+        self
+    }
+
+    /// Add a file field.
+    pub fn file(mut self, name: &str, filename: &str, content_type: &str, data: &[u8]) -> Self {
+        self.part(&format!(
+            "Content-Disposition: form-data; name=\"{name}\"; filename=\"{filename}\"\r\n\
+             Content-Type: {content_type}\r\n"
+        ));
+        self.body.extend_from_slice(data);
+        self.body.extend_from_slice(b"\r\n");
+        self
+    }
+
+    /// Return the request `Content-Type` and body.
+    pub fn finish(mut self) -> (String, Vec<u8>) {
+        self.body
+            .extend_from_slice(format!("--{}--\r\n", self.boundary).as_bytes());
+        (
+            format!("multipart/form-data; boundary={}", self.boundary),
+            self.body,
+        )
+    }
+
+    /// Start a new part with the given headers.
+    fn part(&mut self, headers: &str) {
+        self.body
+            .extend_from_slice(format!("--{}\r\n{headers}\r\n", self.boundary).as_bytes());
+    }
+}
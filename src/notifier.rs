@@ -0,0 +1,284 @@
+// rustymode: Fork of bombuscv, originally an OpenCV-based motion detection/recording software built for research on bumblebees.
+// Originally developed as bombuscv by Marco Radocchia (C) 2022
+// Modified and renamed to rustymode by Dmitry Sobolev (C) 2025
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use crate::{
+    alert::{Alert, Attachment, Severity},
+    events::MotionEvent,
+};
+use chrono::{DateTime, Local};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Seconds waited for the clip of an ended motion event before alerting without it.
+pub const CLIP_WAIT: i64 = 10;
+
+/// Ended motion event, waiting for its clip.
+///
+/// # Fields
+/// * event: ended motion event
+/// * deadline: DateTime after which the event is alerted without clip
+struct Ended {
+    event: MotionEvent,
+    deadline: DateTime<Local>,
+}
+
+/// Motion alerts scheduling: decides when motion started/ended alerts are sent, applying the
+/// cooldown, pairing ended events with their clip and batching events into digests.
+///
+/// # Fields
+/// * camera: camera name
+/// * cooldown: minimum time between two motion started alerts
+/// * digest: digest interval, replacing the per event alerts if set
+/// * last_started: start of the last event alerted
+/// * alerted: start of the event in progress, if alerted
+/// * last_clip: last finalized clip, with the DateTime of its last frame
+/// * pending: ended events waiting for their clip
+/// * digest_events: events of the next digest, with their clip
+/// * digest_start: DateTime the next digest starts from
+pub struct Notifier {
+    camera: String,
+    cooldown: chrono::Duration,
+    digest: Option<chrono::Duration>,
+    last_started: Option<DateTime<Local>>,
+    alerted: Option<DateTime<Local>>,
+    last_clip: Option<(PathBuf, DateTime<Local>)>,
+    pending: Vec<Ended>,
+    digest_events: Vec<(MotionEvent, Option<PathBuf>)>,
+    digest_start: DateTime<Local>,
+}
+
+impl Notifier {
+    /// Create an instance of the notifier.
+    ///
+    /// # Parameters
+    /// * camera: camera name
+    /// * cooldown: minimum time between two motion started alerts
+    /// * digest: digest interval (`None` for per event alerts)
+    /// * now: current DateTime, the first digest starts from
+    pub fn new(
+        camera: &str,
+        cooldown: chrono::Duration,
+        digest: Option<chrono::Duration>,
+        now: DateTime<Local>,
+    ) -> Self {
+        Self {
+            camera: camera.to_string(),
+            cooldown,
+            digest,
+            last_started: None,
+            alerted: None,
+            last_clip: None,
+            pending: Vec::new(),
+            digest_events: Vec::new(),
+            digest_start: now,
+        }
+    }
+
+    /// Return whether the start of the motion event is alerted: never in digest mode, nor
+    /// within the cooldown of the last alerted event.
+    pub fn started(&mut self, event: &MotionEvent) -> bool {
+        if self.digest.is_some() {
+            return false;
+        }
+        if let Some(last) = self.last_started
+            && event.start - last < self.cooldown
+        {
+            return false;
+        }
+
+        self.last_started = Some(event.start);
+        self.alerted = Some(event.start);
+        true
+    }
+
+    /// Queue an ended motion event until its clip is finalized. Events whose start wasn't
+    /// alerted are dropped, unless in digest mode.
+    pub fn ended(&mut self, event: MotionEvent, now: DateTime<Local>) -> Vec<Alert> {
+        let alerted = self.alerted == Some(event.start);
+        if alerted {
+            self.alerted = None;
+        }
+        if !alerted && self.digest.is_none() {
+            return Vec::new();
+        }
+
+        // The clip may be finalized right before the event end is reported.
+        let clip = self
+            .last_clip
+            .as_ref()
+            .filter(|(_, until)| *until >= event.end)
+            .map(|(clip, _)| clip.clone());
+        match clip {
+            Some(clip) => self.release(vec![(event, Some(clip))]),
+            None => {
+                self.pending.push(Ended {
+                    event,
+                    deadline: now + chrono::Duration::seconds(CLIP_WAIT),
+                });
+                Vec::new()
+            }
+        }
+    }
+
+    /// Attach a finalized clip to the ended events started before `until`, the DateTime of its
+    /// last frame, returning their alerts.
+    pub fn clip(&mut self, clip: &Path, until: DateTime<Local>) -> Vec<Alert> {
+        self.last_clip = Some((clip.to_path_buf(), until));
+        let (ready, pending): (Vec<Ended>, Vec<Ended>) = self
+            .pending
+            .drain(..)
+            .partition(|ended| ended.event.start <= until);
+        self.pending = pending;
+        self.release(
+            ready
+                .into_iter()
+                .map(|ended| (ended.event, Some(clip.to_path_buf())))
+                .collect(),
+        )
+    }
+
+    /// Return the alerts due: ended events whose clip wait is over and the digest.
+    pub fn poll(&mut self, now: DateTime<Local>) -> Vec<Alert> {
+        let (ready, pending): (Vec<Ended>, Vec<Ended>) =
+            self.pending.drain(..).partition(|ended| ended.deadline <= now);
+        self.pending = pending;
+        let mut alerts = self.release(
+            ready
+                .into_iter()
+                .map(|ended| (ended.event, None))
+                .collect(),
+        );
+
+        if let Some(digest) = self.digest
+            && now - self.digest_start >= digest
+        {
+            alerts.extend(self.digest(now));
+        }
+        alerts
+    }
+
+    /// Return the remaining alerts, not waiting for clips nor for the digest interval.
+    pub fn flush(&mut self, now: DateTime<Local>) -> Vec<Alert> {
+        let ready = self
+            .pending
+            .drain(..)
+            .map(|ended| (ended.event, None))
+            .collect();
+        let mut alerts = self.release(ready);
+        if self.digest.is_some() {
+            alerts.extend(self.digest(now));
+        }
+        alerts
+    }
+
+    /// Return the time until the next alert is due, if any is scheduled.
+    pub fn next_due(&self, now: DateTime<Local>) -> Option<std::time::Duration> {
+        self.pending
+            .iter()
+            .map(|ended| ended.deadline)
+            .chain(self.digest.map(|digest| self.digest_start + digest))
+            .min()
+            .map(|due| (due - now).to_std().unwrap_or_default())
+    }
+
+    /// Alert the ended events, or keep them for the digest.
+    fn release(&mut self, events: Vec<(MotionEvent, Option<PathBuf>)>) -> Vec<Alert> {
+        if self.digest.is_some() {
+            self.digest_events.extend(events);
+            return Vec::new();
+        }
+        events
+            .into_iter()
+            .map(|(event, clip)| ended_alert(&event, clip))
+            .collect()
+    }
+
+    /// Return the digest of the events since the last one (none if no event occurred), and
+    /// start the next one.
+    fn digest(&mut self, now: DateTime<Local>) -> Option<Alert> {
+        let since = std::mem::replace(&mut self.digest_start, now);
+        let events = std::mem::take(&mut self.digest_events);
+        let peak = events
+            .iter()
+            .map(|(event, _)| event)
+            .max_by(|a, b| a.peak_score.total_cmp(&b.peak_score))?;
+
+        let mut body = format!(
+            "{} motion events since {}, peak score {:.3}",
+            events.len(),
+            since.format("%H:%M:%S"),
+            peak.peak_score
+        );
+        for (event, clip) in &events {
+            body.push_str(&format!(
+                "\n{} {:.1}s score {:.3}{}",
+                event.start.format("%H:%M:%S"),
+                seconds(event),
+                event.peak_score,
+                clip_name(clip.as_deref())
+            ));
+        }
+
+        let mut alert =
+            Alert::new("Motion digest", Severity::Info, now, &self.camera).with_body(&body);
+        if let Some(snapshot) = snapshot(peak) {
+            alert = alert.with_attachment(snapshot);
+        }
+        Some(alert)
+    }
+}
+
+/// Return the duration of the event in seconds.
+fn seconds(event: &MotionEvent) -> f64 {
+    event.duration().num_milliseconds() as f64 / 1000.
+}
+
+/// Return the `, clip <file name>` suffix of alert bodies (empty without clip).
+fn clip_name(clip: Option<&Path>) -> String {
+    clip.and_then(Path::file_name)
+        .map(|name| format!(", clip {}", name.to_string_lossy()))
+        .unwrap_or_default()
+}
+
+/// Return the saved snapshot of the event as attachment.
+fn snapshot(event: &MotionEvent) -> Option<Attachment> {
+    let path = event.snapshot.as_ref()?;
+    let name = path.file_name()?.to_string_lossy();
+    fs::read(path).ok().map(|data| Attachment::jpeg(&name, data))
+}
+
+/// Return the motion ended alert of the event: duration, peak score, clip and snapshot.
+pub fn ended_alert(event: &MotionEvent, clip: Option<PathBuf>) -> Alert {
+    let body = format!(
+        "duration {:.1}s, peak score {:.3}{}",
+        seconds(event),
+        event.peak_score,
+        clip_name(clip.as_deref())
+    );
+    let mut alert = Alert::new("Motion ended", Severity::Info, event.end, &event.camera)
+        .with_body(&body)
+        .with_event_id(event.id);
+    if let Some(clip) = clip {
+        alert = alert.with_clip(clip);
+    }
+    if let Some(snapshot) = snapshot(event) {
+        alert = alert.with_attachment(snapshot);
+    }
+    alert
+}
@@ -0,0 +1,213 @@
+// rustymode: Fork of bombuscv, originally an OpenCV-based motion detection/recording software built for research on bumblebees.
+// Originally developed as bombuscv by Marco Radocchia (C) 2022
+// Modified and renamed to rustymode by Dmitry Sobolev (C) 2025
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use crate::{
+    config::RetentionSettings, error::ErrorKind, events::EventDb, metadata::sidecar_path,
+    Container,
+};
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Bytes in a megabyte.
+const MB: u64 = 1024 * 1024;
+
+/// Delay before measuring the free space again once the disk is critically full.
+const DISK_FULL_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Recording or snapshot file managed by the retention policy.
+///
+/// # Fields
+/// * path: file path
+/// * size: file size in bytes
+/// * modified: last modification time
+struct Recording {
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+}
+
+/// Recordings retention manager: deletes the oldest recordings in the output directory once the
+/// total size, age or free space limits are crossed.
+///
+/// Only the files created by rustymode are deleted: recordings with a JSON sidecar and the clips
+/// & snapshots referenced by the event database.
+///
+/// # Fields
+/// * directory: output video directory
+/// * max_total_size: maximum total size of the recordings in bytes (0 for no limit)
+/// * max_age: maximum age of the recordings (`None` for no limit)
+/// * min_free_space: free space in bytes to keep available on the disk (0 for no limit)
+/// * critical_free_space: free space in bytes below which no new clip is started
+/// * events: event database the deleted clips & snapshots are cleared from
+/// * disk_full: critically full disk error, reported without measuring again until the instant
+pub struct Retention {
+    directory: PathBuf,
+    max_total_size: u64,
+    max_age: Option<Duration>,
+    min_free_space: u64,
+    critical_free_space: u64,
+    events: Option<EventDb>,
+    disk_full: Option<(Instant, String)>,
+}
+
+impl Retention {
+    /// Create an instance of the retention manager.
+    ///
+    /// # Parameters
+    /// * directory: output video directory
+    /// * settings: retention limits
+    pub fn new(directory: &Path, settings: &RetentionSettings) -> Self {
+        Self {
+            directory: directory.to_path_buf(),
+            max_total_size: settings.max_total_size * MB,
+            max_age: (settings.max_age > 0)
+                .then(|| Duration::from_secs(u64::from(settings.max_age) * 24 * 60 * 60)),
+            min_free_space: settings.min_free_space * MB,
+            critical_free_space: settings.critical_free_space * MB,
+            events: None,
+            disk_full: None,
+        }
+    }
+
+    /// Manage the clips & snapshots referenced by the given event database as well, clearing
+    /// their references once deleted.
+    pub fn with_events(mut self, events: EventDb) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Delete the oldest recordings until every limit is satisfied, returning the deleted paths.
+    ///
+    /// Returns `ErrorKind::DiskFull` if the free space is still below the critical threshold
+    /// afterwards: no new clip should be started then. The error is then returned without
+    /// checking the disk again for `DISK_FULL_BACKOFF`.
+    pub fn enforce(&mut self) -> Result<Vec<PathBuf>, ErrorKind> {
+        if let Some((retry_at, msg)) = &self.disk_full
+            && Instant::now() < *retry_at
+        {
+            return Err(ErrorKind::DiskFull(msg.clone()));
+        }
+        self.disk_full = None;
+
+        let mut recordings = self.recordings()?;
+        let mut total_size: u64 = recordings.iter().map(|rec| rec.size).sum();
+        let mut free_space = self.free_space()?;
+        let now = SystemTime::now();
+        let mut removed = Vec::new();
+
+        // Recordings are sorted oldest first.
+        for recording in recordings.drain(..) {
+            let too_old = self.max_age.is_some_and(|max_age| {
+                now.duration_since(recording.modified)
+                    .is_ok_and(|age| age > max_age)
+            });
+            let too_big = self.max_total_size > 0 && total_size > self.max_total_size;
+            let too_full = self.min_free_space > 0 && free_space < self.min_free_space;
+            if !(too_old || too_big || too_full) {
+                break;
+            }
+
+            if fs::remove_file(&recording.path).is_ok() {
+                // Snapshots have no sidecar, clips may lack it if it couldn't be written.
+                if is_video(&recording.path) {
+                    let _ = fs::remove_file(sidecar_path(&recording.path));
+                }
+                // Best effort: a stale reference must not prevent recording.
+                if let Some(events) = &self.events {
+                    let _ = events.forget_file(&recording.path);
+                }
+                total_size -= recording.size;
+                free_space += recording.size;
+                removed.push(recording.path);
+            }
+        }
+
+        // Measure again: other processes may be writing to the same disk.
+        let free_space = self.free_space()?;
+        if free_space < self.critical_free_space {
+            let msg = format!(
+                "only {}MB left on {}, not starting a new clip",
+                free_space / MB,
+                self.directory.display()
+            );
+            self.disk_full = Some((Instant::now() + DISK_FULL_BACKOFF, msg.clone()));
+            return Err(ErrorKind::DiskFull(msg));
+        }
+
+        Ok(removed)
+    }
+
+    /// Return the free space on the output directory disk, in bytes.
+    fn free_space(&self) -> Result<u64, ErrorKind> {
+        fs2::available_space(&self.directory).map_err(|e| {
+            ErrorKind::RetentionErr(format!("{}: {e}", self.directory.display()))
+        })
+    }
+
+    /// Return the recordings in the output directory and the files referenced by the event
+    /// database, sorted oldest first.
+    fn recordings(&self) -> Result<Vec<Recording>, ErrorKind> {
+        let entries = fs::read_dir(&self.directory).map_err(|e| {
+            ErrorKind::RetentionErr(format!("{}: {e}", self.directory.display()))
+        })?;
+        let mut paths: BTreeSet<PathBuf> = entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| is_recording(path))
+            .collect();
+        if let Some(events) = &self.events {
+            paths.extend(events.files()?);
+        }
+
+        let mut recordings: Vec<Recording> = paths
+            .into_iter()
+            .filter_map(|path| {
+                let meta = fs::metadata(&path).ok().filter(|meta| meta.is_file())?;
+                Some(Recording {
+                    size: meta.len(),
+                    modified: meta.modified().ok()?,
+                    path,
+                })
+            })
+            .collect();
+        recordings.sort_by_key(|rec| rec.modified);
+
+        Ok(recordings)
+    }
+}
+
+/// Return whether the path has a video extension (any supported container).
+fn is_video(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.parse::<Container>().is_ok())
+}
+
+/// Return whether the path is a rustymode video recording: video file with a JSON sidecar, hidden
+/// files excluded.
+fn is_recording(path: &Path) -> bool {
+    let hidden = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_none_or(|name| name.starts_with('.'));
+
+    path.is_file() && !hidden && is_video(path) && sidecar_path(path).is_file()
+}
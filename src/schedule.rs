@@ -0,0 +1,297 @@
+// rustymode: Fork of bombuscv, originally an OpenCV-based motion detection/recording software built for research on bumblebees.
+// Originally developed as bombuscv by Marco Radocchia (C) 2022
+// Modified and renamed to rustymode by Dmitry Sobolev (C) 2025
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use crate::error::ErrorKind;
+use chrono::{DateTime, Datelike, Local, NaiveTime, Weekday};
+use chrono_tz::Tz;
+use serde::{de, Deserialize, Deserializer, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Name of the arming state file, in the output directory.
+pub const STATE_FILE: &str = "arming.json";
+
+/// Interval between two reads of the arming state file by a running instance.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Custom deserializer for week days (`mon`, `monday`, ...).
+fn deserialize_days<'de, D>(days: D) -> Result<Vec<Weekday>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(days)?
+        .iter()
+        .map(|day| {
+            day.parse()
+                .map_err(|_| de::Error::custom(format!("invalid week day '{day}'")))
+        })
+        .collect()
+}
+
+/// Custom deserializer for `HH:MM` times of the day.
+fn deserialize_time<'de, D>(time: D) -> Result<NaiveTime, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let time = String::deserialize(time)?;
+    NaiveTime::parse_from_str(&time, "%H:%M")
+        .map_err(|_| de::Error::custom(format!("invalid time '{time}', expected HH:MM")))
+}
+
+/// Default start and end of time ranges.
+fn midnight() -> NaiveTime {
+    NaiveTime::from_hms_opt(0, 0, 0).expect("valid time")
+}
+
+/// Weekly time range. Ranges ending before their start continue the next day, and ranges
+/// ending at their start last 24 hours: `00:00`-`00:00` is the whole day.
+///
+/// # Fields
+/// * days: week days the range starts on (every day if empty)
+/// * start: start time of the day
+/// * end: end time of the day
+/// * cameras: cameras the range applies to (every camera if empty)
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TimeRange {
+    #[serde(default, deserialize_with = "deserialize_days")]
+    pub days: Vec<Weekday>,
+    #[serde(default = "midnight", deserialize_with = "deserialize_time")]
+    pub start: NaiveTime,
+    #[serde(default = "midnight", deserialize_with = "deserialize_time")]
+    pub end: NaiveTime,
+    #[serde(default)]
+    pub cameras: Vec<String>,
+}
+
+impl TimeRange {
+    /// Return whether the range applies to the camera.
+    pub fn applies_to(&self, camera: &str) -> bool {
+        self.cameras.is_empty() || self.cameras.iter().any(|name| name == camera)
+    }
+
+    /// Return whether the time of the week day falls within the range.
+    pub fn contains(&self, day: Weekday, time: NaiveTime) -> bool {
+        let starts_on = |day: Weekday| self.days.is_empty() || self.days.contains(&day);
+        match self.start < self.end {
+            true => starts_on(day) && self.start <= time && time < self.end,
+            // Overnight range: from the start on the day until the end on the next day.
+            false => {
+                (starts_on(day) && time >= self.start)
+                    || (starts_on(day.pred()) && time < self.end)
+            }
+        }
+    }
+}
+
+/// Alerting & recording schedules (`[schedule]` table).
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ScheduleSettings {
+    /// Timezone of the time ranges, as IANA name (local time if empty).
+    pub timezone: String,
+
+    /// Time ranges the alerts are armed within (always armed if none applies to the camera).
+    pub alerts: Vec<TimeRange>,
+
+    /// Time ranges the video is recorded within (always recorded if none applies to the camera).
+    pub recording: Vec<TimeRange>,
+}
+
+/// Manual arming state of the alerts of a camera.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ArmState {
+    /// Follow the alerts schedule.
+    #[default]
+    Auto,
+    /// Alerts armed, regardless of the schedule.
+    Armed,
+    /// Alerts disarmed, regardless of the schedule.
+    Disarmed,
+}
+
+impl Display for ArmState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Auto => write!(f, "auto"),
+            Self::Armed => write!(f, "armed"),
+            Self::Disarmed => write!(f, "disarmed"),
+        }
+    }
+}
+
+/// Manual arming state of a camera, as stored in the state file.
+///
+/// # Fields
+/// * state: arming state
+/// * since: DateTime the state was set (RFC 3339)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ArmRecord {
+    pub state: ArmState,
+    pub since: String,
+}
+
+/// Read the manual arming states of the cameras from the state file in `directory`.
+pub fn read_states(directory: &Path) -> Result<BTreeMap<String, ArmRecord>, ErrorKind> {
+    let path = directory.join(STATE_FILE);
+    match fs::read_to_string(&path) {
+        Ok(json) => serde_json::from_str(&json).map_err(|e| {
+            ErrorKind::ScheduleErr(format!("invalid state file '{}': {e}", path.display()))
+        }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(ErrorKind::ScheduleErr(format!(
+            "unable to read state file '{}': {e}",
+            path.display()
+        ))),
+    }
+}
+
+/// Set the manual arming state of the camera in the state file in `directory`: `auto` removes
+/// the override.
+pub fn set_state(directory: &Path, camera: &str, state: ArmState) -> Result<(), ErrorKind> {
+    let mut states = read_states(directory)?;
+    match state {
+        ArmState::Auto => states.remove(camera),
+        state => states.insert(
+            camera.to_string(),
+            ArmRecord {
+                state,
+                since: Local::now().to_rfc3339(),
+            },
+        ),
+    };
+
+    // Replace the file at once, so that running instances never read it half written.
+    let path = directory.join(STATE_FILE);
+    let tmp = path.with_extension("json.tmp");
+    let json = serde_json::to_string_pretty(&states)
+        .map_err(|e| ErrorKind::ScheduleErr(e.to_string()))?;
+    fs::write(&tmp, json)
+        .and_then(|_| fs::rename(&tmp, &path))
+        .map_err(|e| {
+            ErrorKind::ScheduleErr(format!("unable to write state file '{}': {e}", path.display()))
+        })
+}
+
+/// Arming of the alerts & recording of a camera: the schedules, overridden by the manual arming
+/// state of the alerts, read again from the state file while running.
+///
+/// # Fields
+/// * camera: camera name
+/// * timezone: timezone of the time ranges (local time if `None`)
+/// * alerts: alerts time ranges of the camera
+/// * recording: recording time ranges of the camera
+/// * directory: directory of the state file
+/// * state: manual arming state of the alerts
+/// * refreshed: Instant the state file was last read
+#[derive(Debug, Clone)]
+pub struct Arming {
+    camera: String,
+    timezone: Option<Tz>,
+    alerts: Vec<TimeRange>,
+    recording: Vec<TimeRange>,
+    directory: PathBuf,
+    state: ArmState,
+    refreshed: Instant,
+}
+
+impl Arming {
+    /// Create an instance of the arming of the camera, reading its manual arming state from the
+    /// state file in `directory`.
+    pub fn new(
+        settings: &ScheduleSettings,
+        camera: &str,
+        directory: &Path,
+    ) -> Result<Self, ErrorKind> {
+        let timezone = match settings.timezone.is_empty() {
+            true => None,
+            false => Some(settings.timezone.parse::<Tz>().map_err(|_| {
+                ErrorKind::ScheduleErr(format!("unknown timezone '{}'", settings.timezone))
+            })?),
+        };
+        let ranges = |ranges: &[TimeRange]| -> Vec<TimeRange> {
+            ranges
+                .iter()
+                .filter(|range| range.applies_to(camera))
+                .cloned()
+                .collect()
+        };
+
+        Ok(Self {
+            camera: camera.to_string(),
+            timezone,
+            alerts: ranges(&settings.alerts),
+            recording: ranges(&settings.recording),
+            directory: directory.to_path_buf(),
+            state: read_states(directory)?
+                .get(camera)
+                .map(|record| record.state)
+                .unwrap_or_default(),
+            refreshed: Instant::now(),
+        })
+    }
+
+    /// Return the manual arming state of the alerts.
+    pub fn state(&self) -> ArmState {
+        self.state
+    }
+
+    /// Return whether the alerts are armed at `now`.
+    pub fn alerts_armed(&mut self, now: DateTime<Local>) -> bool {
+        self.refresh();
+        match self.state {
+            ArmState::Armed => true,
+            ArmState::Disarmed => false,
+            ArmState::Auto => self.scheduled(&self.alerts, now),
+        }
+    }
+
+    /// Return whether the video is recorded at `now`.
+    pub fn recording(&self, now: DateTime<Local>) -> bool {
+        self.scheduled(&self.recording, now)
+    }
+
+    /// Return whether `now` falls within the time ranges (always if there is none).
+    fn scheduled(&self, ranges: &[TimeRange], now: DateTime<Local>) -> bool {
+        let now = match self.timezone {
+            Some(timezone) => now.with_timezone(&timezone).naive_local(),
+            None => now.naive_local(),
+        };
+        let (day, time) = (now.weekday(), now.time());
+        ranges.is_empty() || ranges.iter().any(|range| range.contains(day, time))
+    }
+
+    /// Read the manual arming state again, at most every `REFRESH_INTERVAL`. The last known
+    /// state is kept if the state file can't be read.
+    fn refresh(&mut self) {
+        if self.refreshed.elapsed() < REFRESH_INTERVAL {
+            return;
+        }
+        self.refreshed = Instant::now();
+        if let Ok(states) = read_states(&self.directory) {
+            self.state = states
+                .get(&self.camera)
+                .map(|record| record.state)
+                .unwrap_or_default();
+        }
+    }
+}
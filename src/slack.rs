@@ -23,13 +23,147 @@
 
 extern crate slack_hook;
 use slack_hook::{Slack, PayloadBuilder, Payload};
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
 
-use crate::{error::ErrorKind, Messenger};
+use crate::{alert::{Alert, Attachment}, error::ErrorKind, Messenger};
+
+/// Default Slack Web API base URL.
+pub const SLACK_API_URL: &str = "https://slack.com/api";
+
+/// Slack alert settings (`[alerts.slack]` table).
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SlackSettings {
+    /// Incoming webhook URL.
+    pub url: String,
+
+    /// Channel the messages are posted to.
+    pub channel: String,
+
+    /// Username the messages are posted as.
+    pub user: String,
+
+    /// Bot token, used to upload motion snapshots through the files API (empty to send text
+    /// only).
+    pub token: String,
+
+    /// Channel id to share motion snapshots to.
+    pub channel_id: String,
+
+    /// Web API base URL.
+    pub api_url: String,
+}
+
+/// Implement the Default trait for SlackSettings.
+impl Default for SlackSettings {
+    /// Default Slack settings.
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            channel: String::from("#cam"),
+            user: String::from("detector"),
+            token: String::new(),
+            channel_id: String::new(),
+            api_url: String::from(SLACK_API_URL),
+        }
+    }
+}
+
+/// Slack Web API response: only the fields used by the files API.
+#[derive(Deserialize)]
+struct ApiResponse {
+    ok: bool,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    upload_url: Option<String>,
+    #[serde(default)]
+    file_id: Option<String>,
+}
+
+impl ApiResponse {
+    /// Turn Slack `ok: false` responses into errors.
+    fn check(self) -> Result<Self, ErrorKind> {
+        match self.ok {
+            true => Ok(self),
+            false => Err(ErrorKind::UnableToSendSlackMessage(
+                self.error.unwrap_or_else(|| "unknown Slack API error".to_string()),
+            )),
+        }
+    }
+}
+
+/// Map HTTP client errors to Slack send errors.
+fn http_err(e: impl ToString) -> ErrorKind {
+    ErrorKind::UnableToSendSlackMessage(e.to_string())
+}
+
+/// Slack Web API client, uploading snapshots through the files API.
+///
+/// # Fields
+/// * token: bot token (`xoxb-...`), with the `files:write` scope
+/// * channel_id: id of the channel to share the snapshots to
+/// * api_url: Web API base URL
+/// * agent: HTTP client
+pub struct SlackFiles {
+    pub token: String,
+    pub channel_id: String,
+    pub api_url: String,
+    agent: ureq::Agent,
+}
+
+impl SlackFiles {
+    /// Upload a file and share it to the channel with the given text as comment.
+    pub fn upload(&self, text: &str, attachment: &Attachment) -> Result<(), ErrorKind> {
+        let auth = format!("Bearer {}", self.token);
+
+        // Reserve an upload URL for the file.
+        let length = attachment.data.len().to_string();
+        let reserved = self
+            .agent
+            .post(&format!("{}/files.getUploadURLExternal", self.api_url))
+            .set("Authorization", &auth)
+            .send_form(&[("filename", &attachment.name), ("length", &length)])
+            .map_err(http_err)?
+            .into_json::<ApiResponse>()
+            .map_err(http_err)?
+            .check()?;
+        let (Some(upload_url), Some(file_id)) = (reserved.upload_url, reserved.file_id) else {
+            return Err(http_err("missing upload URL in Slack response"));
+        };
+
+        // Upload the file content.
+        self.agent
+            .post(&upload_url)
+            .set("Content-Type", &attachment.content_type)
+            .send_bytes(&attachment.data)
+            .map_err(http_err)?;
+
+        // Share the file to the channel.
+        self.agent
+            .post(&format!("{}/files.completeUploadExternal", self.api_url))
+            .set("Authorization", &auth)
+            .send_json(json!({
+                "files": [{ "id": file_id, "title": "Motion snapshot" }],
+                "channel_id": self.channel_id,
+                "initial_comment": text,
+            }))
+            .map_err(http_err)?
+            .into_json::<ApiResponse>()
+            .map_err(http_err)?
+            .check()?;
+
+        Ok(())
+    }
+}
 
 pub struct SlackMessenger {
     pub slack: Slack,
     pub channel: String,
     pub username: String,
+    pub files: Option<SlackFiles>,
 }
 pub fn new(slack_url: &str, slack_channel: &str, slack_user: &str) -> Result<SlackMessenger, ErrorKind> {
    let slack = Slack::new(slack_url)
@@ -37,6 +171,7 @@ pub fn new(slack_url: &str, slack_channel: &str, slack_user: &str) -> Result<Sla
            slack: s,
            channel: slack_channel.to_string(),
            username: slack_user.to_string(),
+           files: None,
        });
    match slack {
         Ok(slack) => Ok(slack),
@@ -44,16 +179,47 @@ pub fn new(slack_url: &str, slack_channel: &str, slack_user: &str) -> Result<Sla
    }
 }
 
-impl Messenger for SlackMessenger {
-    fn send(&mut self, payload: Payload) -> Result<(), ErrorKind> {
-        let res = &self.slack.send(&payload);
-        match res {
-            Ok(_) => Ok(()),
-            Err(e) => Err(ErrorKind::UnableToSendSlackMessage(e.to_string())),
-        }
+/// Create the Slack messenger described by the settings, uploading snapshots through the files
+/// API if a bot token is configured.
+pub fn from_settings(settings: &SlackSettings) -> Result<SlackMessenger, ErrorKind> {
+    if settings.url.is_empty() {
+        return Err(ErrorKind::CreateSlackClientErr("missing Slack webhook URL".to_string()));
+    }
+
+    if !settings.token.is_empty() && settings.channel_id.is_empty() {
+        return Err(ErrorKind::CreateSlackClientErr(
+            "missing Slack channel_id to share the snapshots to".to_string(),
+        ));
     }
 
-   fn payload(&self, text: String) -> Result<Payload, ErrorKind> {
+    let messenger = new(&settings.url, &settings.channel, &settings.user)?;
+    match settings.token.is_empty() {
+        true => Ok(messenger),
+        false => Ok(messenger.with_files_api(&settings.token, &settings.channel_id, &settings.api_url)),
+    }
+}
+
+impl SlackMessenger {
+    /// Upload snapshots through the Slack files API, authenticated with the given bot token.
+    ///
+    /// # Parameters
+    /// * token: bot token (`xoxb-...`), with the `files:write` scope
+    /// * channel_id: id of the channel to share the snapshots to
+    /// * api_url: Web API base URL (see `SLACK_API_URL`)
+    pub fn with_files_api(mut self, token: &str, channel_id: &str, api_url: &str) -> Self {
+        self.files = Some(SlackFiles {
+            token: token.to_string(),
+            channel_id: channel_id.to_string(),
+            api_url: api_url.trim_end_matches('/').to_string(),
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(30))
+                .build(),
+        });
+        self
+    }
+
+    /// Build the incoming webhook payload of a text message.
+    fn payload(&self, text: String) -> Result<Payload, ErrorKind> {
         let payload = PayloadBuilder::new()
             .text(text)
             .channel(&self.channel)
@@ -64,4 +230,25 @@ impl Messenger for SlackMessenger {
             Err(_) => Err(ErrorKind::CreateSlackPayloadErr),
         }
     }
-}
\ No newline at end of file
+}
+
+impl Messenger for SlackMessenger {
+    fn name(&self) -> &str {
+        "slack"
+    }
+
+    fn send(&mut self, alert: &Alert) -> Result<(), ErrorKind> {
+        let text = alert.text();
+        match (&self.files, alert.snapshot()) {
+            (Some(files), Some(snapshot)) => files.upload(&text, snapshot),
+            // No bot token: webhooks can't carry files, send the text only.
+            _ => {
+                let payload = self.payload(text)?;
+                match self.slack.send(&payload) {
+                    Ok(_) => Ok(()),
+                    Err(e) => Err(ErrorKind::UnableToSendSlackMessage(e.to_string())),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,153 @@
+// rustymode: Fork of bombuscv, originally an OpenCV-based motion detection/recording software built for research on bumblebees.
+// Originally developed as bombuscv by Marco Radocchia (C) 2022
+// Modified and renamed to rustymode by Dmitry Sobolev (C) 2025
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use crate::{error::ErrorKind, BoundingBox, Detection, Frame};
+use chrono::{DateTime, Local};
+use opencv::{
+    core::{Scalar, Vector},
+    imgcodecs::{self, IMWRITE_JPEG_QUALITY},
+    imgproc::{rectangle, LineTypes},
+    prelude::Mat,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Encode a frame as JPEG with the given quality (0-100), the same way the streamer does.
+pub fn encode_jpeg(frame: &Mat, quality: u8) -> Result<Vec<u8>, ErrorKind> {
+    let mut buf = Vector::new();
+    let params = Vector::from_slice(&[IMWRITE_JPEG_QUALITY, quality.into()]);
+    match imgcodecs::imencode(".jpg", frame, &mut buf, &params) {
+        Ok(true) => Ok(buf.to_vec()),
+        Ok(false) => Err(ErrorKind::SnapshotErr("unable to encode JPEG".to_string())),
+        Err(e) => Err(ErrorKind::SnapshotErr(e.to_string())),
+    }
+}
+
+/// Draw motion bounding boxes on a frame.
+pub fn draw_boxes(frame: &mut Mat, boxes: &[BoundingBox]) -> Result<(), ErrorKind> {
+    for bbox in boxes {
+        rectangle(
+            frame,
+            (*bbox).into(),
+            Scalar::new(0., 0., 255., 0.), // Box color.
+            2,                             // Thickness.
+            LineTypes::LINE_8 as i32,
+            0,
+        )
+        .map_err(|e| ErrorKind::SnapshotErr(e.to_string()))?;
+    }
+
+    Ok(())
+}
+
+/// Event snapshot: the highest scoring frame of a motion event.
+///
+/// # Fields
+/// * frame: the video frame
+/// * detection: motion detection result of the frame
+#[derive(Debug)]
+pub struct Snapshot {
+    pub frame: Mat,
+    pub detection: Detection,
+}
+
+/// Keep the highest scoring frame of each motion event and save it as JPEG snapshot.
+///
+/// # Fields
+/// * path: snapshot file path, formatted with the event start date&time
+/// * draw_boxes: draw motion bounding boxes on the snapshot
+/// * quality: JPEG quality (0-100)
+/// * best: highest scoring frame of the motion event in progress
+pub struct Snapshotter {
+    path: String,
+    draw_boxes: bool,
+    quality: u8,
+    best: Option<Snapshot>,
+}
+
+impl Snapshotter {
+    /// Create an instance of the snapshotter, creating the snapshots directory if needed.
+    ///
+    /// # Parameters
+    /// * directory: snapshots directory
+    /// * format: snapshot filename format (see
+    ///   <https://docs.rs/chrono/latest/chrono/format/strftime/index.html> for valid specifiers)
+    /// * draw_boxes: draw motion bounding boxes on the snapshot
+    /// * quality: JPEG quality (0-100)
+    pub fn new(
+        directory: &Path,
+        format: &str,
+        draw_boxes: bool,
+        quality: u8,
+    ) -> Result<Self, ErrorKind> {
+        fs::create_dir_all(directory)
+            .map_err(|e| ErrorKind::SnapshotErr(format!("{}: {e}", directory.display())))?;
+
+        Ok(Self {
+            path: directory
+                .join(Path::new(format).with_extension("jpg"))
+                .to_str()
+                .expect("invalid UTF-8 snapshot path")
+                .to_string(),
+            draw_boxes,
+            quality,
+            best: None,
+        })
+    }
+
+    /// Keep the frame if it scores higher than the current best frame of the event.
+    pub fn update(&mut self, frame: &Frame, detection: &Detection) {
+        if self
+            .best
+            .as_ref()
+            .is_some_and(|best| best.detection.score >= detection.score)
+        {
+            return;
+        }
+
+        self.best = Some(Snapshot {
+            frame: frame.frame.clone(),
+            detection: detection.clone(),
+        });
+    }
+
+    /// Return the best frame of the motion event in progress, if any.
+    pub fn best(&self) -> Option<&Snapshot> {
+        self.best.as_ref()
+    }
+
+    /// Save the best frame of the motion event started at `start` as JPEG, returning its path
+    /// and resetting the snapshotter for the next event.
+    pub fn save(&mut self, start: DateTime<Local>) -> Result<Option<PathBuf>, ErrorKind> {
+        let Some(mut snapshot) = self.best.take() else {
+            return Ok(None);
+        };
+
+        if self.draw_boxes {
+            draw_boxes(&mut snapshot.frame, &snapshot.detection.boxes)?;
+        }
+
+        let path = PathBuf::from(start.format(&self.path).to_string());
+        let jpeg = encode_jpeg(&snapshot.frame, self.quality)?;
+        fs::write(&path, jpeg)
+            .map_err(|e| ErrorKind::SnapshotErr(format!("{}: {e}", path.display())))?;
+
+        Ok(Some(path))
+    }
+}
@@ -0,0 +1,216 @@
+// rustymode: Fork of bombuscv, originally an OpenCV-based motion detection/recording software built for research on bumblebees.
+// Originally developed as bombuscv by Marco Radocchia (C) 2022
+// Modified and renamed to rustymode by Dmitry Sobolev (C) 2025
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use crate::{
+    alert::{Alert, Attachment},
+    error::ErrorKind,
+    multipart::Form,
+    Messenger,
+};
+use serde::Deserialize;
+use std::{
+    fmt::{self, Display, Formatter},
+    time::Duration,
+};
+use url::Url;
+
+/// Default Telegram Bot API base URL.
+pub const TELEGRAM_API_URL: &str = "https://api.telegram.org";
+
+/// Telegram chat: numeric id or `@channelusername`.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum ChatId {
+    Id(i64),
+    Username(String),
+}
+
+impl Display for ChatId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Id(id) => write!(f, "{id}"),
+            Self::Username(username) => write!(f, "{username}"),
+        }
+    }
+}
+
+/// Telegram alert settings (`[alerts.telegram]` table).
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TelegramSettings {
+    /// Bot token, as given by @BotFather.
+    pub token: String,
+
+    /// Chats the alerts are sent to.
+    pub chat_ids: Vec<ChatId>,
+
+    /// Bot API base URL.
+    pub api_url: String,
+
+    /// Request timeout in seconds.
+    pub timeout: u16,
+}
+
+/// Implement the Default trait for TelegramSettings.
+impl Default for TelegramSettings {
+    /// Default Telegram settings.
+    fn default() -> Self {
+        Self {
+            token: String::new(),
+            chat_ids: Vec::new(),
+            api_url: String::from(TELEGRAM_API_URL),
+            timeout: 30,
+        }
+    }
+}
+
+/// Bot API response: only the fields used to report errors.
+#[derive(Deserialize)]
+struct ApiResponse {
+    ok: bool,
+    #[serde(default)]
+    description: Option<String>,
+}
+
+/// Telegram Bot API messenger: sends the alert text with `sendMessage`, or the snapshot with
+/// the text as caption with `sendPhoto`, to every configured chat.
+///
+/// # Fields
+/// * token: bot token
+/// * chat_ids: chats the alerts are sent to
+/// * api_url: Bot API base URL
+/// * agent: HTTP client
+pub struct TelegramMessenger {
+    token: String,
+    chat_ids: Vec<ChatId>,
+    api_url: String,
+    agent: ureq::Agent,
+}
+
+/// Create the Telegram messenger described by the settings.
+pub fn from_settings(settings: &TelegramSettings) -> Result<TelegramMessenger, ErrorKind> {
+    if settings.token.is_empty() {
+        return Err(ErrorKind::CreateTelegramClientErr("missing bot token".to_string()));
+    }
+    if settings.chat_ids.is_empty() {
+        return Err(ErrorKind::CreateTelegramClientErr("missing chat ids".to_string()));
+    }
+    Url::parse(&settings.api_url).map_err(|e| {
+        ErrorKind::CreateTelegramClientErr(format!("invalid Bot API URL: {e}"))
+    })?;
+
+    Ok(TelegramMessenger {
+        token: settings.token.clone(),
+        chat_ids: settings.chat_ids.clone(),
+        api_url: settings.api_url.trim_end_matches('/').to_string(),
+        agent: ureq::AgentBuilder::new()
+            .timeout(Duration::from_secs(settings.timeout.into()))
+            .build(),
+    })
+}
+
+impl TelegramMessenger {
+    /// Return the URL of a Bot API method.
+    fn method_url(&self, method: &str) -> String {
+        format!("{}/bot{}/{method}", self.api_url, self.token)
+    }
+
+    /// Send a text message to the chat.
+    fn send_message(&self, chat_id: &ChatId, text: &str) -> Result<(), ErrorKind> {
+        let response = self
+            .agent
+            .post(&self.method_url("sendMessage"))
+            .send_form(&[("chat_id", &chat_id.to_string()), ("text", text)]);
+        self.check(response)
+    }
+
+    /// Send a photo to the chat, with the text as caption.
+    fn send_photo(&self, chat_id: &ChatId, text: &str, photo: &Attachment) -> Result<(), ErrorKind> {
+        let (content_type, body) = Form::new()
+            .text("chat_id", &chat_id.to_string())
+            .text("caption", text)
+            .file("photo", &photo.name, &photo.content_type, &photo.data)
+            .finish();
+        let response = self
+            .agent
+            .post(&self.method_url("sendPhoto"))
+            .set("Content-Type", &content_type)
+            .send_bytes(&body);
+        self.check(response)
+    }
+
+    /// Turn HTTP errors and `ok: false` responses into errors, keeping the token out of them.
+    fn check(&self, response: Result<ureq::Response, ureq::Error>) -> Result<(), ErrorKind> {
+        let api_response = match response {
+            Ok(response) => response.into_json::<ApiResponse>(),
+            // The Bot API describes the error in the body of 4xx/5xx responses.
+            Err(ureq::Error::Status(code, response)) => response
+                .into_json::<ApiResponse>()
+                .map(|api_response| ApiResponse {
+                    description: api_response
+                        .description
+                        .or_else(|| Some(format!("HTTP status {code}"))),
+                    ..api_response
+                }),
+            Err(e) => return Err(self.error(e)),
+        }
+        .map_err(|e| self.error(e))?;
+
+        match api_response.ok {
+            true => Ok(()),
+            false => Err(ErrorKind::UnableToSendTelegramMessage(
+                api_response
+                    .description
+                    .unwrap_or_else(|| "unknown Bot API error".to_string()),
+            )),
+        }
+    }
+
+    /// Map HTTP client errors to Telegram send errors, redacting the bot token.
+    fn error(&self, e: impl ToString) -> ErrorKind {
+        ErrorKind::UnableToSendTelegramMessage(e.to_string().replace(&self.token, "<token>"))
+    }
+}
+
+impl Messenger for TelegramMessenger {
+    fn name(&self) -> &str {
+        "telegram"
+    }
+
+    fn send(&mut self, alert: &Alert) -> Result<(), ErrorKind> {
+        let text = alert.text();
+
+        // Keep sending to the other chats if one fails.
+        let errors: Vec<String> = self
+            .chat_ids
+            .iter()
+            .filter_map(|chat_id| {
+                match alert.snapshot() {
+                    Some(snapshot) => self.send_photo(chat_id, &text, snapshot),
+                    None => self.send_message(chat_id, &text),
+                }
+                .err()
+                .map(|e| format!("chat {chat_id}: {e}"))
+            })
+            .collect();
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(ErrorKind::UnableToSendTelegramMessage(errors.join("; "))),
+        }
+    }
+}
@@ -21,10 +21,153 @@
 //! Motion Detection, Video Streaming and Alerting with Rust.
 
 
-use crate::{Codec, Config, Grabber, Local, MotionDetector, Path, Writer};
-use bombuscv_rs::Frame;
+use crate::{Args, Config, Grabber, Local, MotionDetector, Parser, Path, Writer};
+use rustymode::{
+    algorithm::{self, Algorithm, MotionAlgorithm},
+    alert::{Alert, Attachment, Dispatcher, Severity},
+    config::{
+        AlertSettings, DetectorSettings, RecordingMode, RecordingSettings, RetentionSettings,
+        SnapshotSettings,
+    },
+    error::ErrorKind,
+    discord::{self, DiscordSettings},
+    email::{self, EmailMessenger, EmailSettings, SmtpTls},
+    matrix::{self, MatrixSettings},
+    events::{self, EventDb, EventFilter, MotionEvent},
+    hooks::{Hook, HookEvent, HookRunner, HookSettings},
+    metadata,
+    mqtt::{self, Topics},
+    notifier::{Notifier, CLIP_WAIT},
+    retention::Retention,
+    schedule::{self, ArmState, Arming, ScheduleSettings},
+    snapshot::Snapshotter,
+    telegram::{self, ChatId, TelegramMessenger, TelegramSettings},
+    webhook::{self, WebhookSettings},
+    zone::{self, Zone, ZoneKind},
+    BoundingBox, Codec, Container, Detection, Frame, Messenger,
+};
+use opencv::{
+    core::{count_non_zero, Rect, Scalar, Size, Vec3b, CV_8UC1, CV_8UC3},
+    imgcodecs::{imread, IMREAD_COLOR},
+    imgproc::{rectangle, LineTypes},
+    prelude::{Mat, MatTraitConst},
+};
+use base64::Engine;
 use directories::BaseDirs;
-use std::{fs, time::Instant};
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    mem,
+    net::TcpListener,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// HTTP request received by the mock server.
+#[derive(Debug, Clone)]
+struct MockRequest {
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl MockRequest {
+    /// Return the value of the given header (case insensitive name).
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Local HTTP server standing in for remote APIs: answers every request with the status & body
+/// returned by the handler for its path, and records the requests.
+struct MockServer {
+    url: String,
+    requests: Arc<Mutex<Vec<MockRequest>>>,
+}
+
+impl MockServer {
+    /// Start the server on a random local port.
+    fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&str, &str) -> (u16, String) + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let base_url = url.clone();
+        let received = Arc::clone(&requests);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                // Request line & headers.
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let path = line.split_whitespace().nth(1).unwrap_or_default().to_string();
+                let mut headers = Vec::new();
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    let Some((key, value)) = line.trim_end().split_once(':') else {
+                        break;
+                    };
+                    headers.push((key.trim().to_string(), value.trim().to_string()));
+                }
+                let request = MockRequest { path, headers, body: Vec::new() };
+
+                // Body.
+                let length = request
+                    .header("content-length")
+                    .and_then(|length| length.parse().ok())
+                    .unwrap_or(0);
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let request = MockRequest { body, ..request };
+
+                let (status, response) = handler(&base_url, &request.path);
+                received.lock().unwrap().push(request);
+                write!(
+                    stream,
+                    "HTTP/1.1 {status} Mock\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{response}",
+                    response.len()
+                )
+                .unwrap();
+            }
+        });
+
+        Self { url, requests }
+    }
+
+    /// Return the requests received so far.
+    fn requests(&self) -> Vec<MockRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// Slack Web API stand-in: successful files API responses.
+#[cfg(feature = "slack")]
+fn slack_api(base_url: &str, path: &str) -> (u16, String) {
+    match path {
+        "/api/files.getUploadURLExternal" => (
+            200,
+            format!(r#"{{"ok":true,"upload_url":"{base_url}/upload/F123","file_id":"F123"}}"#),
+        ),
+        "/upload/F123" => (200, "OK - 4".to_string()),
+        "/api/files.completeUploadExternal" => (200, r#"{"ok":true,"files":[]}"#.to_string()),
+        _ => (404, "{}".to_string()),
+    }
+}
 
 #[test]
 fn sync_frame_processing_avg_time() {
@@ -52,6 +195,16 @@ fn sync_frame_processing_avg_time() {
         slack_user: "detector".to_string(),
         streamer_image_encode: ".jpeg".to_string(),
         streamer_listener: "127.0.0.1:8740".to_string(),
+        detector: DetectorSettings::default(),
+        recording: RecordingSettings::default(),
+        retention: RetentionSettings::default(),
+        snapshot: SnapshotSettings::default(),
+        alerts: AlertSettings::default(),
+        mqtt: None,
+        hooks: HookSettings::default(),
+        schedule: ScheduleSettings::default(),
+        mask_preview: None,
+        camera: String::new(),
     };
 
     // Format video file path as <config.directory/date&time>.
@@ -87,16 +240,17 @@ fn sync_frame_processing_avg_time() {
     .unwrap();
 
     // Instance of the motion detector.
-    let mut detector = MotionDetector::new();
+    let mut detector = MotionDetector::new(config.detector.clone()).unwrap();
 
     // Instance of the frame writer.
     let mut writer = Writer::new(
         &filename,
-        Codec::XVID,
+        config.recording.codec,
         grabber.get_fps(),
         grabber.get_size(),
         config.overlay,
         config.overlay_border,
+        &config.recording,
     )
     .unwrap();
 
@@ -113,14 +267,14 @@ fn sync_frame_processing_avg_time() {
     // Save the start time.
     let start = Instant::now();
     for frame in frames {
-        match detector.detect_motion(frame) {
-            Ok(frame) => {
-                if let Some(frame) = frame {
-                    // If frame is detected, write it to the file.
-                    writer.write(frame).unwrap();
-                    // Count the detected frames.
+        match detector.detect_motion(&frame) {
+            Ok(detection) => {
+                // Count the detected frames.
+                if detection.is_some() {
                     detected_frames += 1;
                 }
+                // Write frames around motion events to the file.
+                writer.write(frame, detection).unwrap();
             }
             Err(_) => panic!("not enaugh frames to run the test!"),
         }
@@ -138,8 +292,1960 @@ fn sync_frame_processing_avg_time() {
     );
     println!("==> max value allowed: {}ms", max);
 
-    // Remove generated video file.
-    fs::remove_file(filename).expect("unable to remove output file.");
+    // Remove generated video file (only recorded if motion was detected).
+    writer.finalize().unwrap();
+    if detected_frames > 0 {
+        fs::remove_file(metadata::sidecar_path(Path::new(&filename)))
+            .expect("unable to remove output sidecar.");
+        fs::remove_file(filename).expect("unable to remove output file.");
+    }
 
     assert!(dur_ns.subsec_micros() <= (max * 1e3) as u32);
 }
+
+#[test]
+fn detector_settings_from_config_and_args() {
+    let config = toml::from_str::<Config>(
+        "[detector]\nthreshold = 50\nblur_kernel = 5\ndilate_iterations = 1",
+    )
+    .unwrap();
+    assert_eq!(config.detector.threshold, 50);
+    assert_eq!(config.detector.blur_kernel, 5);
+    assert_eq!(config.detector.dilate_iterations, 1);
+
+    // Blur kernel size must be odd.
+    assert!(toml::from_str::<Config>("[detector]\nblur_kernel = 4").is_err());
+    assert!(toml::from_str::<Config>("[detector]\nblur_kernel = 0").is_err());
+    assert!(Args::try_parse_from(["rustymode", "--blur-kernel", "4"]).is_err());
+
+    // CLI options override the configuration file.
+    let args = Args::try_parse_from(["rustymode", "-t", "80", "--dilate-iterations", "2"]).unwrap();
+    let config = config.override_with_args(args);
+    assert_eq!(config.detector.threshold, 80);
+    assert_eq!(config.detector.blur_kernel, 5);
+    assert_eq!(config.detector.dilate_iterations, 2);
+}
+
+#[test]
+fn detector_threshold() {
+    // Square 40 gray levels brighter than the background.
+    let background = gray_frame(100., &[]);
+    let moved = gray_frame(100., &[(Rect::new(20, 10, 12, 12), 140.)]);
+    let detect_with = |threshold| {
+        let mut detector = MotionDetector::new(DetectorSettings {
+            threshold,
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(detect(&mut detector, background.clone()).is_none());
+        detect(&mut detector, moved.clone())
+    };
+
+    assert!(detect_with(30).is_some());
+    assert!(detect_with(60).is_none());
+}
+
+/// Seed a fresh absdiff detector with a plain frame, then detect motion on a frame with the given
+/// white squares.
+fn detect_squares(settings: DetectorSettings, squares: &[Rect]) -> Option<Detection> {
+    let mut detector = MotionDetector::new(settings).unwrap();
+    assert!(detect(&mut detector, gray_frame(100., &[])).is_none());
+    let squares: Vec<(Rect, f64)> = squares.iter().map(|square| (*square, 255.)).collect();
+    detect(&mut detector, gray_frame(100., &squares))
+}
+
+#[test]
+fn detector_contour_filters() {
+    // After blur, threshold & dilate: ~121px small blob, ~361px big blobs (3072px frame).
+    let small = Rect::new(6, 36, 4, 4);
+    let big = Rect::new(6, 6, 12, 12);
+    let other = Rect::new(40, 20, 12, 12);
+
+    // Blobs smaller than min_contour_area are ignored.
+    let settings = DetectorSettings {
+        min_contour_area: 200.,
+        ..Default::default()
+    };
+    assert!(detect_squares(settings.clone(), &[small]).is_none());
+    assert_eq!(detect_squares(settings, &[small, big]).unwrap().contours, 1);
+
+    // Fewer qualifying blobs than min_contours.
+    let settings = DetectorSettings {
+        min_contours: 2,
+        ..Default::default()
+    };
+    assert!(detect_squares(settings.clone(), &[big]).is_none());
+    assert_eq!(detect_squares(settings, &[big, other]).unwrap().contours, 2);
+
+    // Changed ratio below min_changed_ratio.
+    let settings = DetectorSettings {
+        min_changed_ratio: 0.2,
+        ..Default::default()
+    };
+    assert!(detect_squares(settings, &[big]).is_none());
+
+    // Every filter satisfied: only the qualifying blobs are reported.
+    let settings = DetectorSettings {
+        min_contour_area: 200.,
+        min_contours: 2,
+        min_changed_ratio: 0.2,
+        ..Default::default()
+    };
+    let detection = detect_squares(settings, &[small, big, other]).unwrap();
+    assert_eq!(detection.contours, 2);
+    assert_eq!(detection.boxes.len(), 2);
+    assert!(detection.score >= 0.2);
+    assert!((detection.score - detection.area / (64. * 48.)).abs() < 1e-9);
+}
+
+#[test]
+fn detector_score_within_zones() {
+    let big = Rect::new(6, 6, 12, 12);
+    let whole = detect_squares(DetectorSettings::default(), &[big]).unwrap();
+
+    // Left half include zone: the same blob covers a larger ratio of the detection area, the
+    // one min_changed_ratio and max_changed_ratio are both measured against.
+    let zone = zone_of("", ZoneKind::Include, &[[0., 0.], [0.5, 0.], [0.5, 1.], [0., 1.]]);
+    let mask = zone::build_mask(std::slice::from_ref(&zone), Size::new(64, 48)).unwrap();
+    let active_area = f64::from(count_non_zero(&mask).unwrap());
+    let settings = DetectorSettings {
+        zones: vec![zone],
+        ..Default::default()
+    };
+    let zoned = detect_squares(settings.clone(), &[big]).unwrap();
+    assert_eq!(zoned.area, whole.area);
+    assert!((zoned.score - zoned.area / active_area).abs() < 1e-9);
+
+    let settings = DetectorSettings {
+        min_changed_ratio: whole.score * 1.5,
+        ..settings
+    };
+    assert!(detect_squares(settings, &[big]).is_some());
+}
+
+/// Build a zone from its polygon vertices.
+fn zone_of(name: &str, kind: ZoneKind, points: &[[f64; 2]]) -> Zone {
+    Zone {
+        name: name.to_string(),
+        kind,
+        points: points.to_vec(),
+    }
+}
+
+/// Return the value of the single channel pixel at `(x, y)`.
+fn pixel(mat: &Mat, x: i32, y: i32) -> u8 {
+    *mat.at_2d::<u8>(y, x).unwrap()
+}
+
+#[test]
+fn zone_mask_include_exclude() {
+    let size = Size::new(100, 50);
+    // Left half of the frame included, its top-left quarter excluded.
+    let left = zone_of("left", ZoneKind::Include, &[[0., 0.], [0.5, 0.], [0.5, 1.], [0., 1.]]);
+    let corner = zone_of("", ZoneKind::Exclude, &[[0., 0.], [0.25, 0.], [0.25, 0.5], [0., 0.5]]);
+
+    // No zones: no mask, motion is detected on the whole frame.
+    assert!(zone::build_mask(&[], size).is_none());
+
+    let mask = zone::build_mask(&[left.clone(), corner.clone()], size).unwrap();
+    assert_eq!(mask.size().unwrap(), size);
+    assert_eq!(pixel(&mask, 10, 10), 0);
+    assert_eq!(pixel(&mask, 40, 10), 255);
+    assert_eq!(pixel(&mask, 10, 40), 255);
+    assert_eq!(pixel(&mask, 80, 25), 0);
+
+    // Exclude zones only: the rest of the frame stays active.
+    let mask = zone::build_mask(&[corner], size).unwrap();
+    assert_eq!(pixel(&mask, 10, 10), 0);
+    assert_eq!(pixel(&mask, 80, 25), 255);
+    assert!(count_non_zero(&mask).unwrap() > 100 * 50 * 3 / 4);
+
+    // Only named include zones get their own mask.
+    let unnamed = zone_of("", ZoneKind::Include, &[[0.5, 0.], [1., 0.], [1., 1.]]);
+    let masks = zone::build_zone_masks(&[left, unnamed], size);
+    assert_eq!(masks.len(), 1);
+    assert_eq!(masks[0].0, "left");
+    assert_eq!(pixel(&masks[0].1, 10, 10), 255);
+    assert_eq!(pixel(&masks[0].1, 80, 25), 0);
+}
+
+/// Return a synthetic 64x48 BGR frame of the given gray level, with filled gray squares.
+fn gray_frame(level: f64, squares: &[(Rect, f64)]) -> Mat {
+    let mut frame = Mat::new_rows_cols_with_default(48, 64, CV_8UC3, Scalar::all(level)).unwrap();
+    for (square, level) in squares {
+        rectangle(&mut frame, *square, Scalar::all(*level), -1, LineTypes::LINE_8 as i32, 0)
+            .unwrap();
+    }
+    frame
+}
+
+/// Feed the frames to the motion detection algorithm, returning the last foreground image.
+fn foreground(algorithm: &mut Box<dyn MotionAlgorithm + Send>, frames: &[Mat]) -> Mat {
+    let mut foreground = Mat::default();
+    for frame in frames {
+        algorithm.apply(frame, &mut foreground).unwrap();
+    }
+    foreground
+}
+
+/// Every motion detection algorithm.
+const ALGORITHMS: [Algorithm; 4] =
+    [Algorithm::Absdiff, Algorithm::RunningAverage, Algorithm::Mog2, Algorithm::Knn];
+
+#[test]
+fn motion_algorithms_foreground() {
+    let object = Rect::new(10, 10, 10, 10);
+    for kind in ALGORITHMS {
+        let settings = DetectorSettings {
+            algorithm: kind,
+            ..Default::default()
+        };
+        let mut algorithm = algorithm::new(&settings).unwrap();
+
+        // Static scene: no change once the background is known.
+        let fg = foreground(&mut algorithm, &vec![gray_frame(100., &[]); 50]);
+        assert_eq!(fg.typ(), CV_8UC1, "{kind:?}");
+        assert_eq!(fg.size().unwrap(), Size::new(64, 48), "{kind:?}");
+        assert_eq!(count_non_zero(&fg).unwrap(), 0, "{kind:?}");
+
+        // Object entering the scene: the foreground covers it exactly.
+        let fg = foreground(&mut algorithm, &[gray_frame(100., &[(object, 255.)])]);
+        assert_eq!(count_non_zero(&fg).unwrap(), 100, "{kind:?}");
+        assert!(pixel(&fg, 15, 15) > 0, "{kind:?}");
+    }
+}
+
+#[test]
+fn motion_algorithms_ignore_shadows() {
+    for kind in [Algorithm::Mog2, Algorithm::Knn] {
+        let settings = DetectorSettings {
+            algorithm: kind,
+            detect_shadows: true,
+            ..Default::default()
+        };
+        let mut algorithm = algorithm::new(&settings).unwrap();
+        foreground(&mut algorithm, &vec![gray_frame(100., &[]); 50]);
+
+        // Object on the left, darker copy of the background (shadow) on the right.
+        let frame = gray_frame(
+            100.,
+            &[(Rect::new(5, 5, 10, 10), 255.), (Rect::new(40, 5, 10, 10), 70.)],
+        );
+        let fg = foreground(&mut algorithm, &[frame]);
+        assert_eq!(pixel(&fg, 10, 10), 255, "{kind:?}");
+        // Shadows are mapped to background, never to the OpenCV shadow value.
+        assert_eq!(pixel(&fg, 45, 10), 0, "{kind:?}");
+        assert_eq!(count_non_zero(&fg).unwrap(), 100, "{kind:?}");
+    }
+}
+
+#[test]
+fn motion_algorithms_reset() {
+    let frames = [
+        gray_frame(200., &[]),
+        gray_frame(200., &[(Rect::new(10, 10, 10, 10), 40.)]),
+    ];
+    for kind in ALGORITHMS {
+        let settings = DetectorSettings {
+            algorithm: kind,
+            ..Default::default()
+        };
+        let mut used = algorithm::new(&settings).unwrap();
+        let mut fresh = algorithm::new(&settings).unwrap();
+        foreground(&mut used, &vec![gray_frame(100., &[]); 50]);
+
+        // Once reset, the background learnt so far is forgotten: the algorithm behaves as a new
+        // instance.
+        used.reset();
+        for frame in &frames {
+            let expected = foreground(&mut fresh, std::slice::from_ref(frame));
+            let actual = foreground(&mut used, std::slice::from_ref(frame));
+            assert_eq!(
+                actual.data_typed::<u8>().unwrap(),
+                expected.data_typed::<u8>().unwrap(),
+                "{kind:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn detector_boxes_in_frame_coordinates() {
+    // 320x180 frames analysed at 64x36: every analysis pixel is a 5x5 block of the frame.
+    let frame = |square: Option<Rect>| {
+        let mut frame =
+            Mat::new_rows_cols_with_default(180, 320, CV_8UC3, Scalar::all(100.)).unwrap();
+        if let Some(square) = square {
+            rectangle(&mut frame, square, Scalar::all(255.), -1, LineTypes::LINE_8 as i32, 0)
+                .unwrap();
+        }
+        frame
+    };
+    // No blur nor dilation: the blob keeps its shape in the analysis frame.
+    let mut detector = MotionDetector::new(DetectorSettings {
+        analysis_width: 64,
+        blur_kernel: 1,
+        dilate_iterations: 0,
+        ..Default::default()
+    })
+    .unwrap();
+    assert!(detect(&mut detector, frame(None)).is_none());
+
+    let detection = detect(&mut detector, frame(Some(Rect::new(100, 50, 50, 40)))).unwrap();
+    assert_eq!(detection.boxes, [BoundingBox { x: 100, y: 50, width: 50, height: 40 }]);
+    // Contour polygon area through the border pixel centers: 9x7 analysis pixels.
+    assert_eq!(detection.area, 63. * 25.);
+}
+
+#[test]
+fn config_rejects_zero_sizes() {
+    let config = toml::from_str::<Config>("[detector]\nanalysis_width = 320").unwrap();
+    assert_eq!(config.detector.analysis_width, 320);
+    assert!(toml::from_str::<Config>("[detector]\nanalysis_width = 0").is_err());
+    assert!(Args::try_parse_from(["rustymode", "--analysis-width", "0"]).is_err());
+
+    let config = toml::from_str::<Config>("[recording]\nsegment_minutes = 1").unwrap();
+    assert_eq!(config.recording.segment_minutes, 1);
+    assert!(toml::from_str::<Config>("[recording]\nsegment_minutes = 0").is_err());
+    assert!(Args::try_parse_from(["rustymode", "--segment-minutes", "0"]).is_err());
+}
+
+/// Run motion detection on a synthetic frame.
+fn detect(detector: &mut MotionDetector, frame: Mat) -> Option<Detection> {
+    detector
+        .detect_motion(&Frame {
+            frame,
+            datetime: Local::now(),
+        })
+        .unwrap()
+}
+
+#[test]
+fn detector_first_frame_seeds_background() {
+    for kind in ALGORITHMS {
+        let mut detector = MotionDetector::new(DetectorSettings {
+            algorithm: kind,
+            ..Default::default()
+        })
+        .unwrap();
+
+        // Nothing to compare the first frame against: never reported as motion.
+        let frame = gray_frame(100., &[(Rect::new(10, 10, 10, 10), 255.)]);
+        assert!(detect(&mut detector, frame).is_none(), "{kind:?}");
+        assert_eq!(detector.stats().frames, 1, "{kind:?}");
+        assert_eq!(detector.stats().detections, 0, "{kind:?}");
+    }
+}
+
+#[test]
+fn detector_luminance_jump_restarts_background() {
+    let object = [(Rect::new(10, 10, 10, 10), 255.)];
+    for kind in [Algorithm::RunningAverage, Algorithm::Mog2] {
+        let mut detector = MotionDetector::new(DetectorSettings {
+            algorithm: kind,
+            max_luminance_jump: 30.,
+            ..Default::default()
+        })
+        .unwrap();
+        for _ in 0..10 {
+            assert!(detect(&mut detector, gray_frame(100., &[])).is_none(), "{kind:?}");
+        }
+
+        // Lights on: the new brightness becomes the background instead of being blended in.
+        assert!(detect(&mut detector, gray_frame(180., &[])).is_none(), "{kind:?}");
+        assert!(detect(&mut detector, gray_frame(180., &[])).is_none(), "{kind:?}");
+        assert!(detect(&mut detector, gray_frame(180., &object)).is_some(), "{kind:?}");
+
+        let stats = detector.stats();
+        assert_eq!(stats.frames, 13, "{kind:?}");
+        assert_eq!(stats.detections, 1, "{kind:?}");
+        assert_eq!(stats.lighting_events, 1, "{kind:?}");
+    }
+}
+
+#[test]
+fn detector_changed_ratio_within_zones() {
+    // Small include zone: top-left eighth of the frame.
+    let zone = zone_of("", ZoneKind::Include, &[[0., 0.], [0.25, 0.], [0.25, 0.5], [0., 0.5]]);
+    let object = [(Rect::new(4, 4, 5, 5), 255.)];
+    for kind in [Algorithm::Absdiff, Algorithm::RunningAverage, Algorithm::Mog2] {
+        let mut detector = MotionDetector::new(DetectorSettings {
+            algorithm: kind,
+            zones: vec![zone.clone()],
+            max_changed_ratio: 0.5,
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(detect(&mut detector, gray_frame(100., &[])).is_none(), "{kind:?}");
+
+        // The whole zone changing is a lighting event, however small the zone is in the frame.
+        assert!(detect(&mut detector, gray_frame(200., &[])).is_none(), "{kind:?}");
+        assert!(detect(&mut detector, gray_frame(200., &[])).is_none(), "{kind:?}");
+        assert_eq!(detector.stats().lighting_events, 1, "{kind:?}");
+
+        // Motion within the zone is still reported.
+        assert!(detect(&mut detector, gray_frame(200., &object)).is_some(), "{kind:?}");
+
+        let stats = detector.stats();
+        assert_eq!(stats.frames, 4, "{kind:?}");
+        assert_eq!(stats.detections, 1, "{kind:?}");
+        assert_eq!(stats.lighting_events, 1, "{kind:?}");
+    }
+}
+
+/// Return an empty directory for the test.
+fn test_directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("rustymode-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+/// Return a synthetic frame captured `millis` milliseconds after 2025-01-06 12:00:00.
+fn frame_at(millis: i64) -> Frame {
+    use chrono::TimeZone;
+    Frame {
+        frame: gray_frame(100., &[]),
+        datetime: Local.with_ymd_and_hms(2025, 1, 6, 12, 0, 0).unwrap()
+            + chrono::Duration::milliseconds(millis),
+    }
+}
+
+/// Build a motion detection of the given score.
+fn detection(score: f64) -> Detection {
+    Detection {
+        datetime: Local::now(),
+        boxes: vec![BoundingBox { x: 10, y: 10, width: 10, height: 10 }],
+        area: 100.,
+        score,
+        contours: 1,
+        zones: Vec::new(),
+    }
+}
+
+/// Create a writer of 64x48 frames at 10 fps, recording MJPG clips named `<format>.avi`.
+fn test_writer(directory: &Path, format: &str, recording: RecordingSettings) -> Writer {
+    let recording = RecordingSettings {
+        codec: Codec::MJPG,
+        container: Container::Avi,
+        ..recording
+    };
+    Writer::new(
+        directory.join(format).with_extension("avi").to_str().unwrap(),
+        recording.codec,
+        10.,
+        Size::new(64, 48),
+        false,
+        0,
+        &recording,
+    )
+    .unwrap()
+}
+
+/// Read the JSON sidecar of a recording.
+fn sidecar(recording: &Path) -> serde_json::Value {
+    serde_json::from_str(&fs::read_to_string(metadata::sidecar_path(recording)).unwrap()).unwrap()
+}
+
+#[test]
+fn writer_pre_roll_and_post_roll() {
+    let directory = test_directory("writer-motion");
+    let recording = RecordingSettings {
+        pre_roll: 1,
+        post_roll: 1,
+        ..Default::default()
+    };
+    let mut writer = test_writer(&directory, "%H%M", recording);
+
+    // No motion: the latest frames are only buffered, nothing is recorded.
+    for i in 0..20 {
+        assert_eq!(writer.write(frame_at(i * 100), None).unwrap(), None);
+    }
+    assert_eq!(fs::read_dir(&directory).unwrap().count(), 0);
+
+    // Motion: the clip starts with the pre-roll frames and goes on for the post-roll.
+    assert_eq!(writer.write(frame_at(2000), Some(detection(0.5))).unwrap(), None);
+    for i in 21..31 {
+        assert_eq!(writer.write(frame_at(i * 100), None).unwrap(), None);
+    }
+    let clip = writer.write(frame_at(3100), None).unwrap().unwrap();
+    assert_eq!(clip, directory.join("1200.avi"));
+    let json = sidecar(&clip);
+    assert_eq!(json["start"], frame_at(1000).datetime.to_rfc3339());
+    assert_eq!(json["end"], frame_at(3100).datetime.to_rfc3339());
+    assert_eq!(json["frame_count"], 22);
+    assert_eq!(json["motion"].as_array().unwrap().len(), 1);
+    assert_eq!(json["motion"][0]["index"], 10);
+
+    // Next event named after the same minute: the finalized clip is never overwritten.
+    assert_eq!(writer.write(frame_at(3200), Some(detection(0.5))).unwrap(), None);
+    let next = writer.finalize().unwrap().unwrap();
+    assert_eq!(next, directory.join("1200-1.avi"));
+    assert_eq!(sidecar(&next)["frame_count"], 1);
+    assert_eq!(sidecar(&clip)["frame_count"], 22);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn writer_continuous_segments() {
+    let directory = test_directory("writer-continuous");
+    let recording = RecordingSettings {
+        mode: RecordingMode::Continuous,
+        segment_minutes: 1,
+        ..Default::default()
+    };
+    let mut writer = test_writer(&directory, "segment", recording);
+
+    // A frame every 10s, motion in the second minute.
+    let mut segments = Vec::new();
+    for i in 0..15 {
+        let motion = (i == 7).then(|| detection(0.5));
+        segments.extend(writer.write(frame_at(i * 10_000), motion).unwrap());
+    }
+    segments.extend(writer.finalize().unwrap());
+
+    // Segments with the same name are suffixed instead of overwritten.
+    assert_eq!(
+        segments,
+        [
+            directory.join("segment.avi"),
+            directory.join("segment-1-motion.avi"),
+            directory.join("segment-1.avi"),
+        ]
+    );
+    let frames: Vec<u64> = segments
+        .iter()
+        .map(|segment| sidecar(segment)["frame_count"].as_u64().unwrap())
+        .collect();
+    assert_eq!(frames, [6, 6, 3]);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn codec_and_container_names() {
+    let codecs = [
+        ("mjpg", Codec::MJPG),
+        ("xvid", Codec::XVID),
+        ("mp4v", Codec::MP4V),
+        ("h264", Codec::H264),
+        ("avc1", Codec::AVC1),
+        ("vp80", Codec::VP80),
+        ("vp90", Codec::VP90),
+    ];
+    for (name, codec) in codecs {
+        assert_eq!(name.parse::<Codec>(), Ok(codec));
+    }
+    assert!("hevc".parse::<Codec>().unwrap_err().contains("unknown codec 'hevc'"));
+    assert!("MJPG".parse::<Codec>().is_err());
+
+    let containers = [
+        ("mkv", Container::Mkv),
+        ("mp4", Container::Mp4),
+        ("avi", Container::Avi),
+        ("webm", Container::Webm),
+    ];
+    for (name, container) in containers {
+        assert_eq!(name.parse::<Container>(), Ok(container));
+        assert_eq!(container.extension(), name);
+    }
+    assert!("mov".parse::<Container>().unwrap_err().contains("unknown container 'mov'"));
+}
+
+#[test]
+fn codec_container_pairs() {
+    assert!(!Codec::VP80.supports(Container::Mp4));
+    assert!(!Codec::VP90.supports(Container::Mp4));
+    assert!(!Codec::AVC1.supports(Container::Webm));
+    assert!(!Codec::H264.supports(Container::Avi));
+    assert!(!Codec::MJPG.supports(Container::Webm));
+    assert!(Codec::VP90.supports(Container::Webm));
+    assert!(Codec::AVC1.supports(Container::Mp4));
+    assert!(Codec::MJPG.supports(Container::Avi));
+    // Every codec can be stored in mkv.
+    for codec in ["mjpg", "xvid", "mp4v", "h264", "avc1", "vp80", "vp90"] {
+        assert!(codec.parse::<Codec>().unwrap().supports(Container::Mkv));
+    }
+}
+
+#[test]
+fn codec_validate() {
+    let directory = test_directory("codec-validate");
+    let size = Size::new(64, 48);
+
+    // Rejected pair: refused without probing the backend.
+    assert!(matches!(
+        Codec::VP80.validate(Container::Mp4, &directory, 10., size),
+        Err(ErrorKind::UnsupportedCodec(_))
+    ));
+
+    // Supported pair: the probe file is opened then removed.
+    Codec::MJPG.validate(Container::Avi, &directory, 10., size).unwrap();
+    assert_eq!(fs::read_dir(&directory).unwrap().count(), 0);
+
+    // Video writer that the backend can't open.
+    assert!(matches!(
+        Codec::MJPG.validate(Container::Avi, &directory.join("missing"), 10., size),
+        Err(ErrorKind::UnsupportedCodec(_))
+    ));
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+/// Create a 1MB file last modified `days` days ago, with a JSON sidecar if requested.
+fn old_file(path: &Path, days: u64, sidecar: bool) -> PathBuf {
+    fs::write(path, vec![0; 1024 * 1024]).unwrap();
+    let modified = SystemTime::now() - Duration::from_secs(days * 24 * 60 * 60);
+    fs::File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+    if sidecar {
+        fs::write(metadata::sidecar_path(path), "{}").unwrap();
+    }
+    path.to_path_buf()
+}
+
+#[test]
+fn retention_max_total_size() {
+    let directory = test_directory("retention-size");
+    let foreign = old_file(&directory.join("holiday.mp4"), 9, false);
+    let oldest = old_file(&directory.join("0900.mkv"), 3, true);
+    let older = old_file(&directory.join("1000.mkv"), 2, true);
+    let newest = old_file(&directory.join("1100.mkv"), 1, true);
+    let settings = RetentionSettings {
+        max_total_size: 2,
+        critical_free_space: 0,
+        ..Default::default()
+    };
+
+    let removed = Retention::new(&directory, &settings).enforce().unwrap();
+    assert_eq!(removed, [oldest.clone()]);
+    assert!(!oldest.exists());
+    assert!(!metadata::sidecar_path(&oldest).exists());
+    // Videos without sidecar weren't recorded by rustymode: neither counted nor deleted.
+    assert!(foreign.exists() && older.exists() && newest.exists());
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn retention_max_age() {
+    let directory = test_directory("retention-age");
+    let foreign = old_file(&directory.join("holiday.mp4"), 9, false);
+    let old = old_file(&directory.join("0900.mkv"), 3, true);
+    let recent = old_file(&directory.join("1000.mkv"), 1, true);
+    let settings = RetentionSettings {
+        max_age: 2,
+        critical_free_space: 0,
+        ..Default::default()
+    };
+
+    let removed = Retention::new(&directory, &settings).enforce().unwrap();
+    assert_eq!(removed, [old]);
+    assert!(foreign.exists() && recent.exists());
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn retention_min_free_space() {
+    let directory = test_directory("retention-free");
+    let foreign = old_file(&directory.join("holiday.mp4"), 9, false);
+    let older = old_file(&directory.join("0900.mkv"), 2, true);
+    let newest = old_file(&directory.join("1000.mkv"), 1, true);
+    // 1EB can't be freed: every recording is deleted, oldest first.
+    let settings = RetentionSettings {
+        min_free_space: 1 << 40,
+        critical_free_space: 0,
+        ..Default::default()
+    };
+
+    let removed = Retention::new(&directory, &settings).enforce().unwrap();
+    assert_eq!(removed, [older, newest]);
+    assert!(foreign.exists());
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn retention_critical_free_space() {
+    let directory = test_directory("retention-critical");
+    let recording = old_file(&directory.join("0900.mkv"), 1, true);
+    let settings = RetentionSettings {
+        critical_free_space: 1 << 40,
+        ..Default::default()
+    };
+    let mut retention = Retention::new(&directory, &settings);
+
+    // No limit crossed: nothing deleted, but no new clip can be started.
+    assert!(matches!(retention.enforce(), Err(ErrorKind::DiskFull(_))));
+    assert!(recording.exists());
+
+    // Backoff: the disk isn't checked again right away (the missing directory would fail).
+    fs::remove_dir_all(&directory).unwrap();
+    assert!(matches!(retention.enforce(), Err(ErrorKind::DiskFull(_))));
+}
+
+#[test]
+fn retention_event_clips_and_snapshots() {
+    let directory = test_directory("retention-events");
+    let snapshots = directory.join("snapshots");
+    fs::create_dir(&snapshots).unwrap();
+    // Clip without sidecar, known from the event database.
+    let clip = old_file(&directory.join("0900.mkv"), 3, false);
+    let snapshot = old_file(&snapshots.join("0900.jpg"), 3, false);
+    let foreign = old_file(&snapshots.join("holiday.jpg"), 3, false);
+
+    let events = EventDb::open(&directory).unwrap();
+    let mut event = motion_event(Local::now() - chrono::Duration::days(3), 5, 0.5);
+    event.id = Some(events.insert_event(&event).unwrap());
+    event.snapshot = Some(snapshot.clone());
+    events.finish_event(&event).unwrap();
+    events
+        .attach_clip("garden", &clip, event.start - chrono::Duration::seconds(1), event.end)
+        .unwrap();
+
+    let settings = RetentionSettings {
+        max_age: 2,
+        critical_free_space: 0,
+        ..Default::default()
+    };
+    let mut retention =
+        Retention::new(&directory, &settings).with_events(EventDb::open(&directory).unwrap());
+    let removed = retention.enforce().unwrap();
+    assert_eq!(removed.len(), 2);
+    assert!(!clip.exists() && !snapshot.exists());
+    assert!(foreign.exists());
+
+    // Deleted files are cleared from the event, which isn't attached to the next clip.
+    let next = directory.join("1000.mkv");
+    events.attach_clip("garden", &next, event.end, Local::now()).unwrap();
+    let records = events.query(&EventFilter::default()).unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].clip, None);
+    assert_eq!(records[0].snapshot, None);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn clip_metadata_sidecar() {
+    assert_eq!(
+        metadata::sidecar_path(Path::new("/clips/2025-01-06T12:00:00.mkv")),
+        PathBuf::from("/clips/2025-01-06T12:00:00.json")
+    );
+
+    let directory = test_directory("clip-metadata");
+    let start = frame_at(0).datetime;
+    let end = frame_at(100).datetime;
+    let mut clip = metadata::ClipMetadata::new(start, "0", 64, 48, 10., Codec::MJPG);
+    clip.push(start, None);
+    clip.push(end, Some(&detection(0.25)));
+
+    let recording = directory.join("clip.avi");
+    assert_eq!(clip.write(&recording).unwrap(), directory.join("clip.json"));
+    let json = sidecar(&recording);
+    assert_eq!(json["start"], start.to_rfc3339());
+    assert_eq!(json["end"], end.to_rfc3339());
+    assert_eq!(json["source"], "0");
+    assert_eq!(json["width"], 64);
+    assert_eq!(json["height"], 48);
+    assert_eq!(json["fps"], 10.);
+    assert_eq!(json["codec"], "mjpg");
+    assert_eq!(json["frame_count"], 2);
+    assert_eq!(json["motion"].as_array().unwrap().len(), 1);
+    assert_eq!(json["motion"][0]["index"], 1);
+    assert_eq!(json["motion"][0]["datetime"], end.to_rfc3339());
+    assert_eq!(json["motion"][0]["score"], 0.25);
+    assert_eq!(
+        json["motion"][0]["boxes"],
+        serde_json::json!([{ "x": 10, "y": 10, "width": 10, "height": 10 }])
+    );
+
+    assert!(matches!(
+        clip.write(&directory.join("missing").join("clip.avi")),
+        Err(ErrorKind::SidecarErr(_))
+    ));
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn writer_returns_clip_without_sidecar() {
+    let directory = test_directory("writer-sidecar");
+    let mut writer = test_writer(&directory, "%H%M", RecordingSettings::default());
+    // A directory in place of the sidecar: it can't be written.
+    fs::create_dir(directory.join("1200.json")).unwrap();
+
+    assert_eq!(writer.write(frame_at(0), Some(detection(0.5))).unwrap(), None);
+    let clip = writer.finalize().unwrap().unwrap();
+    assert_eq!(clip, directory.join("1200.avi"));
+    assert!(clip.is_file());
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+/// Record a 2s motion event of `camera` starting `offset` seconds after 2025-01-06 12:00:00,
+/// returning its id.
+fn record_event(events: &EventDb, camera: &str, offset: i64, peak_score: f64) -> i64 {
+    let mut event = motion_event(frame_at(offset * 1000).datetime, 2, peak_score);
+    event.camera = camera.to_string();
+    let id = events.insert_event(&event).unwrap();
+    event.id = Some(id);
+    events.finish_event(&event).unwrap();
+    id
+}
+
+/// Return the ids of the events matching the filter.
+fn event_ids(events: &EventDb, filter: EventFilter) -> Vec<i64> {
+    events
+        .query(&filter)
+        .unwrap()
+        .iter()
+        .map(|record| record.id)
+        .collect()
+}
+
+#[test]
+fn event_db_schema_and_queries() {
+    let directory = test_directory("event-db");
+    let events = EventDb::open(&directory).unwrap();
+    // Opening an existing database keeps it.
+    let events_again = EventDb::open(&directory).unwrap();
+    let conn = rusqlite::Connection::open(directory.join(events::EVENTS_DB)).unwrap();
+    let tables: Vec<String> = conn
+        .prepare(
+            "SELECT name FROM sqlite_master
+             WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+        )
+        .unwrap()
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(tables, ["alerts", "events"]);
+
+    let start = frame_at(0).datetime;
+    let garden = record_event(&events, "garden", 0, 0.2);
+    let door = record_event(&events, "door", 60, 0.5);
+    let late = record_event(&events_again, "garden", 120, 0.8);
+    // Event still in progress.
+    let running = events
+        .insert_event(&motion_event(frame_at(180_000).datetime, 0, 0.1))
+        .unwrap();
+    events.insert_alert(Some(garden), "slack", start, None).unwrap();
+    events
+        .insert_alert(
+            Some(garden),
+            "webhook",
+            start + chrono::Duration::seconds(1),
+            Some("HTTP 500"),
+        )
+        .unwrap();
+
+    assert_eq!(event_ids(&events, EventFilter::default()), [garden, door, late, running]);
+    // Time range bounds are inclusive.
+    let filter = EventFilter {
+        since: Some(frame_at(60_000).datetime),
+        until: Some(frame_at(120_000).datetime),
+        ..Default::default()
+    };
+    assert_eq!(event_ids(&events, filter), [door, late]);
+    let filter = EventFilter {
+        camera: Some("garden".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(event_ids(&events, filter), [garden, late, running]);
+    let filter = EventFilter {
+        min_score: 0.5,
+        ..Default::default()
+    };
+    assert_eq!(event_ids(&events, filter), [door, late]);
+
+    let records = events.query(&EventFilter::default()).unwrap();
+    assert_eq!(records[0].camera, "garden");
+    assert_eq!(records[0].start, start.to_rfc3339());
+    assert_eq!(records[0].end, Some((start + chrono::Duration::seconds(2)).to_rfc3339()));
+    assert_eq!(records[0].duration, Some(2.));
+    assert_eq!(records[0].peak_score, 0.2);
+    assert_eq!(records[0].alerts.len(), 2);
+    assert_eq!(records[0].alerts[0].backend, "slack");
+    assert_eq!(records[0].alerts[0].status, "sent");
+    assert_eq!(records[0].alerts[0].error, None);
+    assert_eq!(records[0].alerts[1].backend, "webhook");
+    assert_eq!(records[0].alerts[1].status, "failed");
+    assert_eq!(records[0].alerts[1].error.as_deref(), Some("HTTP 500"));
+    assert!(records[1].alerts.is_empty());
+    assert_eq!(records[3].end, None);
+    assert_eq!(records[3].duration, None);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn event_db_attach_clip() {
+    let directory = test_directory("event-db-clip");
+    let events = EventDb::open(&directory).unwrap();
+    record_event(&events, "garden", 0, 0.5);
+    record_event(&events, "garden", 10, 0.5);
+    record_event(&events, "door", 15, 0.5);
+    record_event(&events, "garden", 20, 0.5);
+
+    // The event starting on the finalization frame belongs to the finalized clip.
+    let first = directory.join("first.mkv");
+    let second = directory.join("second.mkv");
+    events
+        .attach_clip("garden", &first, frame_at(-1000).datetime, frame_at(10_000).datetime)
+        .unwrap();
+    events
+        .attach_clip("garden", &second, frame_at(10_000).datetime, frame_at(30_000).datetime)
+        .unwrap();
+
+    let clips: Vec<Option<String>> = events
+        .query(&EventFilter::default())
+        .unwrap()
+        .into_iter()
+        .map(|record| record.clip)
+        .collect();
+    let first = Some(first.to_string_lossy().to_string());
+    let second = Some(second.to_string_lossy().to_string());
+    assert_eq!(clips, [first.clone(), first, None, second]);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+/// Return a synthetic frame of the given gray level, captured at 2025-01-06 12:00:00.
+fn gray_frame_at(level: f64) -> Frame {
+    Frame {
+        frame: gray_frame(level, &[]),
+        datetime: frame_at(0).datetime,
+    }
+}
+
+/// Return the BGR pixel at `(x, y)`.
+fn bgr(mat: &Mat, x: i32, y: i32) -> Vec3b {
+    *mat.at_2d::<Vec3b>(y, x).unwrap()
+}
+
+#[test]
+fn snapshotter_keeps_best_frame() {
+    let directory = test_directory("snapshots");
+    let start = frame_at(0).datetime;
+    let mut snapshotter = Snapshotter::new(&directory.join("snapshots"), "%H%M", false, 90).unwrap();
+    assert!(snapshotter.best().is_none());
+    assert_eq!(snapshotter.save(start).unwrap(), None);
+
+    // The first of the highest scoring frames is kept.
+    for (level, score) in [(50., 0.2), (150., 0.6), (200., 0.6), (100., 0.4)] {
+        snapshotter.update(&gray_frame_at(level), &detection(score));
+    }
+    let best = snapshotter.best().unwrap();
+    assert_eq!(best.detection.score, 0.6);
+    assert_eq!(bgr(&best.frame, 0, 0)[0], 150);
+
+    let path = snapshotter.save(start).unwrap().unwrap();
+    assert_eq!(path, directory.join("snapshots").join("1200.jpg"));
+    let jpeg = imread(path.to_str().unwrap(), IMREAD_COLOR).unwrap();
+    assert_eq!(jpeg.size().unwrap(), Size::new(64, 48));
+    assert!(bgr(&jpeg, 32, 24)[0].abs_diff(150) <= 2);
+
+    // Saving resets the snapshotter for the next event.
+    assert!(snapshotter.best().is_none());
+    assert_eq!(snapshotter.save(start).unwrap(), None);
+
+    // Motion bounding boxes drawn in red.
+    let mut snapshotter = Snapshotter::new(&directory, "boxes", true, 100).unwrap();
+    snapshotter.update(&gray_frame_at(0.), &detection(0.5));
+    let path = snapshotter.save(start).unwrap().unwrap();
+    let jpeg = imread(path.to_str().unwrap(), IMREAD_COLOR).unwrap();
+    let edge = bgr(&jpeg, 10, 15);
+    assert!(edge[2] > 128 && edge[2] > edge[0] + 64);
+    assert!(bgr(&jpeg, 40, 40)[2] < 32);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+/// Build a motion alert carrying the given snapshot.
+fn motion_alert(snapshot: &[u8]) -> Alert {
+    Alert::new("Motion Detected", Severity::Warning, Local::now(), "garden")
+        .with_attachment(Attachment::jpeg("motion.jpg", snapshot.to_vec()))
+}
+
+#[cfg(feature = "slack")]
+#[test]
+fn slack_snapshot_upload() {
+    let server = MockServer::start(slack_api);
+    let mut messenger = rustymode::slack::new(&server.url, "#cam", "detector")
+        .unwrap()
+        .with_files_api("xoxb-test", "C123", &format!("{}/api", server.url));
+
+    let alert = motion_alert(&[0xff, 0xd8, 0xff, 0xd9]);
+    messenger.send(&alert).unwrap();
+
+    let requests = server.requests();
+    let paths: Vec<&str> = requests.iter().map(|req| req.path.as_str()).collect();
+    assert_eq!(
+        paths,
+        [
+            "/api/files.getUploadURLExternal",
+            "/upload/F123",
+            "/api/files.completeUploadExternal"
+        ]
+    );
+
+    // Upload URL is reserved with the snapshot size, authenticated with the bot token.
+    assert_eq!(requests[0].header("authorization"), Some("Bearer xoxb-test"));
+    assert!(String::from_utf8_lossy(&requests[0].body).contains("length=4"));
+
+    // Snapshot is uploaded as is.
+    assert_eq!(requests[1].body, [0xff, 0xd8, 0xff, 0xd9]);
+
+    // File is shared to the channel with the message as comment.
+    let complete: serde_json::Value = serde_json::from_slice(&requests[2].body).unwrap();
+    assert_eq!(complete["channel_id"], "C123");
+    assert_eq!(complete["initial_comment"], alert.text());
+    assert_eq!(complete["files"][0]["id"], "F123");
+}
+
+#[cfg(feature = "slack")]
+#[test]
+fn slack_snapshot_upload_api_error() {
+    let server = MockServer::start(|_, _| (200, r#"{"ok":false,"error":"invalid_auth"}"#.to_string()));
+    let mut messenger = rustymode::slack::new(&server.url, "#cam", "detector")
+        .unwrap()
+        .with_files_api("xoxb-invalid", "C123", &format!("{}/api", server.url));
+
+    let result = messenger.send(&motion_alert(&[0xff, 0xd8]));
+
+    assert!(matches!(
+        result,
+        Err(ErrorKind::UnableToSendSlackMessage(msg)) if msg == "invalid_auth"
+    ));
+    // Nothing is uploaded once the upload URL is refused.
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[cfg(feature = "slack")]
+#[test]
+fn slack_token_requires_channel_id() {
+    let settings = rustymode::slack::SlackSettings {
+        url: "https://hooks.slack.com/services/T0/B0/X".to_string(),
+        token: "xoxb-test".to_string(),
+        ..Default::default()
+    };
+    assert!(matches!(
+        rustymode::slack::from_settings(&settings),
+        Err(ErrorKind::CreateSlackClientErr(_))
+    ));
+
+    // Without token, alerts are sent through the webhook only.
+    let settings = rustymode::slack::SlackSettings {
+        token: String::new(),
+        ..settings
+    };
+    assert!(rustymode::slack::from_settings(&settings).is_ok());
+}
+
+/// Build a motion detection for the webhook tests.
+fn webhook_detection() -> Detection {
+    Detection {
+        datetime: Local::now(),
+        boxes: vec![BoundingBox { x: 10, y: 20, width: 30, height: 40 }],
+        area: 1200.,
+        score: 0.25,
+        contours: 1,
+        zones: Vec::new(),
+    }
+}
+
+#[test]
+fn webhook_signed_event() {
+    let server = MockServer::start(|_, _| (200, String::new()));
+    let settings = WebhookSettings {
+        url: format!("{}/hook", server.url),
+        headers: [("Authorization".to_string(), "Bearer token".to_string())].into(),
+        secret: "s3cret".to_string(),
+        ..Default::default()
+    };
+    let mut webhook = webhook::from_settings(&settings).unwrap();
+
+    let alert = motion_alert(&[0xff, 0xd8])
+        .with_event_id(Some(7))
+        .with_detection(webhook_detection());
+    webhook.send(&alert).unwrap();
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    let request = &requests[0];
+    assert_eq!(request.path, "/hook");
+    assert_eq!(request.header("authorization"), Some("Bearer token"));
+    assert_eq!(request.header("content-type"), Some("application/json"));
+    let signature = webhook::sign("s3cret", &request.body);
+    assert_eq!(request.header("x-rustymode-signature"), Some(signature.as_str()));
+
+    let json: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    assert_eq!(json["camera"], "garden");
+    assert_eq!(json["title"], "Motion Detected");
+    assert_eq!(json["severity"], "warning");
+    assert_eq!(json["event_id"], 7);
+    assert_eq!(json["score"], 0.25);
+    assert_eq!(json["boxes"][0]["width"], 30);
+    assert_eq!(json["snapshot"], "/9g=");
+}
+
+#[test]
+fn webhook_retries_server_errors() {
+    let attempts = AtomicUsize::new(0);
+    let server = MockServer::start(move |_, _| {
+        match attempts.fetch_add(1, Ordering::SeqCst) {
+            0 => (500, String::new()),
+            _ => (200, String::new()),
+        }
+    });
+    let settings = WebhookSettings {
+        url: server.url.clone(),
+        retry_delay: 10,
+        ..Default::default()
+    };
+    let mut webhook = webhook::from_settings(&settings).unwrap();
+
+    let alert = Alert::new("Motion Detected", Severity::Warning, Local::now(), "garden")
+        .with_detection(webhook_detection());
+    webhook.send(&alert).unwrap();
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    // Unsigned without a secret, no snapshot when none is given.
+    assert_eq!(requests[1].header("x-rustymode-signature"), None);
+    let json: serde_json::Value = serde_json::from_slice(&requests[1].body).unwrap();
+    assert!(json.get("snapshot").is_none());
+}
+
+#[test]
+fn webhook_gives_up_on_client_errors() {
+    let server = MockServer::start(|_, _| (404, String::new()));
+    let settings = WebhookSettings {
+        url: server.url.clone(),
+        retry_delay: 10,
+        ..Default::default()
+    };
+    let mut webhook = webhook::from_settings(&settings).unwrap();
+
+    let alert = Alert::new("Recording suspended", Severity::Critical, Local::now(), "garden");
+
+    assert!(matches!(webhook.send(&alert), Err(ErrorKind::WebhookErr(_))));
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn webhook_requires_url() {
+    // A [alerts.webhook] table is enabled: its url can't be left empty.
+    let config = toml::from_str::<Config>("[alerts.webhook]\nurl = \"\"").unwrap();
+    let result = webhook::from_settings(config.alerts.webhook.as_ref().unwrap());
+    assert!(matches!(result, Err(ErrorKind::WebhookErr(msg)) if msg == "missing webhook URL"));
+
+    let settings = WebhookSettings {
+        url: "not a url".to_string(),
+        ..Default::default()
+    };
+    assert!(matches!(webhook::from_settings(&settings), Err(ErrorKind::WebhookErr(_))));
+}
+
+/// Messenger recording the received alerts, failing if asked to.
+struct MockMessenger {
+    name: &'static str,
+    fail: bool,
+    received: Arc<Mutex<Vec<String>>>,
+}
+
+impl Messenger for MockMessenger {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn send(&mut self, alert: &Alert) -> Result<(), ErrorKind> {
+        self.received.lock().unwrap().push(alert.title.clone());
+        match self.fail {
+            true => Err(ErrorKind::AlertErr("unreachable".to_string())),
+            false => Ok(()),
+        }
+    }
+}
+
+#[test]
+fn dispatcher_fans_out_alerts() {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let mut dispatcher = Dispatcher::new();
+    for (name, fail) in [("first", false), ("second", true), ("third", false)] {
+        dispatcher.push(Box::new(MockMessenger {
+            name,
+            fail,
+            received: Arc::clone(&received),
+        }));
+    }
+
+    let alert = Alert::new("Motion Detected", Severity::Warning, Local::now(), "garden");
+    let deliveries = dispatcher.dispatch(&alert);
+
+    // A failing messenger doesn't prevent delivery to the others.
+    assert_eq!(received.lock().unwrap().len(), 3);
+    let results: Vec<(&str, bool)> = deliveries
+        .iter()
+        .map(|delivery| (delivery.backend.as_str(), delivery.result.is_ok()))
+        .collect();
+    assert_eq!(results, [("first", true), ("second", false), ("third", true)]);
+}
+
+#[test]
+fn alerts_are_optional() {
+    // No `[alerts]` table: no messenger.
+    let config: Config = toml::from_str("").unwrap();
+    assert!(config.alerts.enabled);
+    assert!(config.alerts.slack.is_none());
+    assert!(config.alerts.webhook.is_none());
+
+    // Messengers are enabled by their own table.
+    let config: Config = toml::from_str(
+        r#"
+        [alerts.webhook]
+        url = "http://localhost:8080/hook"
+        "#,
+    )
+    .unwrap();
+    assert!(config.alerts.slack.is_none());
+    assert_eq!(config.alerts.webhook.unwrap().url, "http://localhost:8080/hook");
+}
+
+/// Build a Telegram messenger targeting the stand-in server.
+fn telegram_messenger(server: &MockServer) -> TelegramMessenger {
+    telegram::from_settings(&TelegramSettings {
+        token: "123:ABC".to_string(),
+        chat_ids: vec![ChatId::Id(-100200), ChatId::Username("@cams".to_string())],
+        api_url: server.url.clone(),
+        ..Default::default()
+    })
+    .unwrap()
+}
+
+#[test]
+fn telegram_send_message_and_photo() {
+    let server = MockServer::start(|_, _| (200, r#"{"ok":true,"result":{}}"#.to_string()));
+    let mut messenger = telegram_messenger(&server);
+
+    // Text only alert.
+    let alert = Alert::new("Recording suspended", Severity::Critical, Local::now(), "garden");
+    messenger.send(&alert).unwrap();
+    // Alert with snapshot.
+    messenger.send(&motion_alert(&[0xff, 0xd8, 0xff, 0xd9])).unwrap();
+
+    // Every alert is sent to every chat.
+    let requests = server.requests();
+    let paths: Vec<&str> = requests.iter().map(|req| req.path.as_str()).collect();
+    assert_eq!(
+        paths,
+        [
+            "/bot123:ABC/sendMessage",
+            "/bot123:ABC/sendMessage",
+            "/bot123:ABC/sendPhoto",
+            "/bot123:ABC/sendPhoto"
+        ]
+    );
+    let message = String::from_utf8_lossy(&requests[0].body);
+    assert!(message.contains("chat_id=-100200"));
+    assert!(message.contains("Recording+suspended"));
+
+    // Snapshot is uploaded as multipart form, the alert text as caption.
+    assert!(requests[3]
+        .header("content-type")
+        .unwrap()
+        .starts_with("multipart/form-data; boundary="));
+    let photo = String::from_utf8_lossy(&requests[3].body);
+    assert!(photo.contains("name=\"chat_id\"\r\n\r\n@cams\r\n"));
+    assert!(photo.contains("name=\"caption\""));
+    assert!(photo.contains("name=\"photo\"; filename=\"motion.jpg\""));
+    assert!(requests[3]
+        .body
+        .windows(4)
+        .any(|bytes| bytes == [0xff, 0xd8, 0xff, 0xd9]));
+}
+
+#[test]
+fn telegram_api_error() {
+    let server = MockServer::start(|_, path| match path.ends_with("sendMessage") {
+        true => (
+            400,
+            r#"{"ok":false,"error_code":400,"description":"Bad Request: chat not found"}"#
+                .to_string(),
+        ),
+        false => (404, String::new()),
+    });
+    let mut messenger = telegram_messenger(&server);
+
+    let alert = Alert::new("Recording suspended", Severity::Critical, Local::now(), "garden");
+    let result = messenger.send(&alert);
+
+    // Both chats are attempted, the error describes each failure.
+    assert_eq!(server.requests().len(), 2);
+    assert!(matches!(
+        result,
+        Err(ErrorKind::UnableToSendTelegramMessage(msg))
+            if msg.contains("chat -100200: Bad Request: chat not found")
+                && msg.contains("chat @cams")
+    ));
+}
+
+#[test]
+fn telegram_requires_chat_ids() {
+    let settings = TelegramSettings {
+        token: "123:ABC".to_string(),
+        ..Default::default()
+    };
+
+    assert!(matches!(
+        telegram::from_settings(&settings),
+        Err(ErrorKind::CreateTelegramClientErr(_))
+    ));
+}
+
+/// Build a Discord messenger targeting the stand-in server.
+fn discord_messenger(server: &MockServer) -> discord::DiscordMessenger {
+    discord::from_settings(&DiscordSettings {
+        url: format!("{}/api/webhooks/1/abc", server.url),
+        ..Default::default()
+    })
+    .unwrap()
+}
+
+#[test]
+fn discord_embedded_snapshot() {
+    let server = MockServer::start(|_, _| (204, String::new()));
+    let mut messenger = discord_messenger(&server);
+
+    messenger.send(&motion_alert(&[0xff, 0xd8, 0xff, 0xd9])).unwrap();
+    let alert = Alert::new("Recording suspended", Severity::Critical, Local::now(), "garden");
+    messenger.send(&alert).unwrap();
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].path, "/api/webhooks/1/abc");
+
+    // Snapshot is uploaded along with the message and embedded as image.
+    assert!(requests[0]
+        .header("content-type")
+        .unwrap()
+        .starts_with("multipart/form-data; boundary="));
+    let form = String::from_utf8_lossy(&requests[0].body);
+    assert!(form.contains("name=\"files[0]\"; filename=\"motion.jpg\""));
+    let payload = form
+        .split("name=\"payload_json\"\r\n\r\n")
+        .nth(1)
+        .and_then(|part| part.split("\r\n").next())
+        .unwrap();
+    let payload: serde_json::Value = serde_json::from_str(payload).unwrap();
+    assert_eq!(payload["username"], "detector");
+    assert_eq!(payload["embeds"][0]["title"], "Motion Detected");
+    assert_eq!(payload["embeds"][0]["image"]["url"], "attachment://motion.jpg");
+
+    // Text only alerts are sent as JSON.
+    let payload: serde_json::Value = serde_json::from_slice(&requests[1].body).unwrap();
+    assert_eq!(payload["embeds"][0]["title"], "Recording suspended");
+    assert_eq!(payload["embeds"][0]["color"], 0xe74c3c);
+    assert!(payload["embeds"][0].get("image").is_none());
+}
+
+#[test]
+fn discord_api_error() {
+    let server = MockServer::start(|_, _| {
+        (401, r#"{"message":"Invalid Webhook Token","code":50027}"#.to_string())
+    });
+    let mut messenger = discord_messenger(&server);
+
+    let alert = Alert::new("Recording suspended", Severity::Critical, Local::now(), "garden");
+
+    assert!(matches!(
+        messenger.send(&alert),
+        Err(ErrorKind::UnableToSendDiscordMessage(msg)) if msg == "Invalid Webhook Token"
+    ));
+}
+
+/// Matrix client-server API stand-in: successful responses.
+fn matrix_api(_: &str, path: &str) -> (u16, String) {
+    match path {
+        path if path.starts_with("/_matrix/media/v3/upload") => {
+            (200, r#"{"content_uri":"mxc://example.org/abc"}"#.to_string())
+        }
+        path if path.starts_with("/_matrix/client/v3/rooms/") => {
+            (200, r#"{"event_id":"$event"}"#.to_string())
+        }
+        _ => (404, r#"{"errcode":"M_UNRECOGNIZED"}"#.to_string()),
+    }
+}
+
+/// Build a Matrix messenger targeting the stand-in server.
+fn matrix_messenger(server: &MockServer) -> matrix::MatrixMessenger {
+    matrix::from_settings(&MatrixSettings {
+        homeserver: server.url.clone(),
+        access_token: "syt_token".to_string(),
+        room_id: "!room:example.org".to_string(),
+        ..Default::default()
+    })
+    .unwrap()
+}
+
+#[test]
+fn matrix_text_and_image() {
+    let server = MockServer::start(matrix_api);
+    let mut messenger = matrix_messenger(&server);
+
+    messenger.send(&motion_alert(&[0xff, 0xd8, 0xff, 0xd9])).unwrap();
+
+    // Text message, snapshot upload, image message.
+    let requests = server.requests();
+    assert_eq!(requests.len(), 3);
+    let room = "/_matrix/client/v3/rooms/!room:example.org/send/m.room.message/";
+    assert!(requests[0].path.starts_with(room));
+    assert_eq!(requests[1].path, "/_matrix/media/v3/upload?filename=motion.jpg");
+    assert!(requests[2].path.starts_with(room));
+    assert_ne!(requests[0].path, requests[2].path);
+    for request in &requests {
+        assert_eq!(request.header("authorization"), Some("Bearer syt_token"));
+    }
+
+    let text: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+    assert_eq!(text["msgtype"], "m.text");
+    assert!(text["body"].as_str().unwrap().ends_with("[garden] Motion Detected"));
+
+    assert_eq!(requests[1].header("content-type"), Some("image/jpeg"));
+    assert_eq!(requests[1].body, [0xff, 0xd8, 0xff, 0xd9]);
+
+    let image: serde_json::Value = serde_json::from_slice(&requests[2].body).unwrap();
+    assert_eq!(image["msgtype"], "m.image");
+    assert_eq!(image["url"], "mxc://example.org/abc");
+    assert_eq!(image["info"]["size"], 4);
+}
+
+#[test]
+fn matrix_api_error() {
+    let server = MockServer::start(|_, _| {
+        (403, r#"{"errcode":"M_FORBIDDEN","error":"not in room"}"#.to_string())
+    });
+    let mut messenger = matrix_messenger(&server);
+
+    let result = messenger.send(&motion_alert(&[0xff, 0xd8]));
+
+    assert!(matches!(
+        result,
+        Err(ErrorKind::UnableToSendMatrixMessage(msg)) if msg == "M_FORBIDDEN: not in room"
+    ));
+    // Nothing is uploaded once the text message is refused.
+    assert_eq!(server.requests().len(), 1);
+}
+
+/// Email received by the SMTP sink.
+#[derive(Debug, Clone, Default)]
+struct SinkMail {
+    auth: Option<String>,
+    from: String,
+    rcpt: Vec<String>,
+    data: String,
+}
+
+/// Local SMTP sink: accepts every email and records it.
+struct SmtpSink {
+    port: u16,
+    mails: Arc<Mutex<Vec<SinkMail>>>,
+}
+
+impl SmtpSink {
+    /// Start the sink on a random local port.
+    fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mails = Arc::new(Mutex::new(Vec::new()));
+
+        let received = Arc::clone(&mails);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut mail = SinkMail::default();
+                write!(stream, "220 sink ESMTP\r\n").unwrap();
+
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 0 {
+                    let command = line.trim_end().to_string();
+                    line.clear();
+                    let reply = match command.split_whitespace().next().unwrap_or_default() {
+                        "EHLO" => "250-sink\r\n250 AUTH PLAIN LOGIN",
+                        "AUTH" => {
+                            mail.auth = command.split_whitespace().nth(2).map(String::from);
+                            "235 2.7.0 Authenticated"
+                        }
+                        "MAIL" => {
+                            mail.from = command;
+                            "250 2.1.0 OK"
+                        }
+                        "RCPT" => {
+                            mail.rcpt.push(command);
+                            "250 2.1.5 OK"
+                        }
+                        "DATA" => {
+                            write!(stream, "354 Go ahead\r\n").unwrap();
+                            while reader.read_line(&mut line).unwrap() > 0 && line != ".\r\n" {
+                                mail.data.push_str(&line);
+                                line.clear();
+                            }
+                            line.clear();
+                            received.lock().unwrap().push(mem::take(&mut mail));
+                            "250 2.0.0 Queued"
+                        }
+                        "QUIT" => {
+                            write!(stream, "221 2.0.0 Bye\r\n").unwrap();
+                            break;
+                        }
+                        _ => "250 OK",
+                    };
+                    write!(stream, "{reply}\r\n").unwrap();
+                }
+            }
+        });
+
+        Self { port, mails }
+    }
+
+    /// Return the emails received so far.
+    fn mails(&self) -> Vec<SinkMail> {
+        self.mails.lock().unwrap().clone()
+    }
+}
+
+/// Build an email messenger targeting the SMTP sink.
+fn email_messenger(sink: &SmtpSink) -> EmailMessenger {
+    email::from_settings(&EmailSettings {
+        host: "127.0.0.1".to_string(),
+        port: Some(sink.port),
+        tls: SmtpTls::None,
+        username: "cam".to_string(),
+        password: "secret".to_string(),
+        from: "rustymode <cam@example.org>".to_string(),
+        to: vec!["oncall@example.org".to_string(), "site@example.org".to_string()],
+        clip_url: "https://nas.example.org/clips/".to_string(),
+        ..Default::default()
+    })
+    .unwrap()
+}
+
+#[test]
+fn email_snapshot_and_clip_link() {
+    let sink = SmtpSink::start();
+    let mut messenger = email_messenger(&sink);
+
+    let alert = motion_alert(&[0xff, 0xd8, 0xff, 0xd9])
+        .with_clip(Path::new("/recordings/2025-01-01T10:00:00.mkv").to_path_buf());
+    messenger.send(&alert).unwrap();
+
+    let mails = sink.mails();
+    assert_eq!(mails.len(), 1);
+    let mail = &mails[0];
+
+    // Authenticated, sent to every recipient.
+    let auth = base64::engine::general_purpose::STANDARD
+        .decode(mail.auth.as_deref().unwrap())
+        .unwrap();
+    assert_eq!(auth, b"\0cam\0secret");
+    assert_eq!(mail.from, "MAIL FROM:<cam@example.org>");
+    assert_eq!(
+        mail.rcpt,
+        ["RCPT TO:<oncall@example.org>", "RCPT TO:<site@example.org>"]
+    );
+
+    // Multipart email with the clip link and the snapshot attached.
+    assert!(mail.data.contains("Subject: [rustymode] garden: Motion Detected"));
+    assert!(mail.data.contains("Content-Type: multipart/mixed"));
+    assert!(mail
+        .data
+        .contains("Clip: https://nas.example.org/clips/2025-01-01T10:00:00.mkv"));
+    assert!(mail.data.contains("Content-Type: image/jpeg"));
+    assert!(mail.data.contains("filename=\"motion.jpg\""));
+    assert!(mail.data.contains("/9j/2Q=="));
+}
+
+#[test]
+fn email_requires_recipients() {
+    let settings = EmailSettings {
+        host: "127.0.0.1".to_string(),
+        from: "cam@example.org".to_string(),
+        ..Default::default()
+    };
+
+    assert!(matches!(
+        email::from_settings(&settings),
+        Err(ErrorKind::CreateEmailClientErr(_))
+    ));
+}
+
+#[test]
+fn mqtt_topics_and_discovery() {
+    let topics = Topics::new("rustymode/", "Front Door");
+    assert_eq!(topics.availability, "rustymode/front_door/availability");
+    assert_eq!(topics.motion, "rustymode/front_door/motion");
+    assert_eq!(topics.event, "rustymode/front_door/event");
+    assert_eq!(topics.snapshot, "rustymode/front_door/snapshot");
+
+    let configs = mqtt::discovery("homeassistant", "Front Door", &topics);
+    let topics_of: Vec<&str> = configs.iter().map(|(topic, _)| topic.as_str()).collect();
+    assert_eq!(
+        topics_of,
+        [
+            "homeassistant/binary_sensor/rustymode_front_door/motion/config",
+            "homeassistant/camera/rustymode_front_door/snapshot/config",
+        ]
+    );
+
+    let motion: serde_json::Value = serde_json::from_str(&configs[0].1).unwrap();
+    assert_eq!(motion["device_class"], "motion");
+    assert_eq!(motion["state_topic"], "rustymode/front_door/motion");
+    assert_eq!(motion["availability_topic"], "rustymode/front_door/availability");
+    assert_eq!(motion["device"]["name"], "Front Door");
+    let camera: serde_json::Value = serde_json::from_str(&configs[1].1).unwrap();
+    assert_eq!(camera["topic"], "rustymode/front_door/snapshot");
+    assert_eq!(camera["device"], motion["device"]);
+}
+
+#[test]
+fn mqtt_event_payload() {
+    let start = Local::now();
+    let event = MotionEvent {
+        id: Some(7),
+        camera: "garden".to_string(),
+        start,
+        end: start + chrono::Duration::milliseconds(2500),
+        peak_score: 0.25,
+        zones: ["door".to_string()].into_iter().collect(),
+        snapshot: None,
+    };
+
+    let payload: serde_json::Value =
+        serde_json::from_str(&mqtt::event_payload("end", &event)).unwrap();
+    assert_eq!(payload["type"], "end");
+    assert_eq!(payload["id"], 7);
+    assert_eq!(payload["camera"], "garden");
+    assert_eq!(payload["duration"], 2.5);
+    assert_eq!(payload["peak_score"], 0.25);
+    assert_eq!(payload["zones"], serde_json::json!(["door"]));
+    assert!(payload["snapshot"].is_null());
+}
+
+#[test]
+fn mqtt_close_with_broker_down() {
+    // Free local port nobody listens on.
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let settings = mqtt::MqttSettings {
+        host: "127.0.0.1".to_string(),
+        port,
+        ..Default::default()
+    };
+    let publisher = mqtt::MqttPublisher::connect(&settings, "garden").unwrap();
+
+    // Fill the request queue: publishing never blocks, the messages are dropped.
+    let event = motion_event(Local::now(), 1, 0.5);
+    for _ in 0..100 {
+        let _ = publisher.motion_ended(&event, Some(vec![0xff, 0xd8]));
+    }
+
+    // Closing doesn't wait for the broker.
+    let start = Instant::now();
+    let _ = publisher.close();
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+/// Build a hooks runner collecting the reported errors.
+fn hook_runner(settings: HookSettings) -> (HookRunner, Arc<Mutex<Vec<String>>>) {
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    let reported = Arc::clone(&errors);
+    let runner = HookRunner::new(&settings, move |e| reported.lock().unwrap().push(e.to_string()))
+        .unwrap();
+    (runner, errors)
+}
+
+#[test]
+fn hook_environment() {
+    let out = std::env::temp_dir().join(format!("rustymode-hook-{}.env", std::process::id()));
+    let (hooks, errors) = hook_runner(HookSettings {
+        on_clip_finalized: format!(
+            "echo \"$RUSTYMODE_HOOK|$RUSTYMODE_CAMERA|$RUSTYMODE_CLIP|$RUSTYMODE_SCORE\" > {}",
+            out.display()
+        ),
+        ..Default::default()
+    });
+    assert_eq!(hooks.names(), ["on_clip_finalized"]);
+
+    // Hooks without command are no-ops.
+    hooks.run(Hook::EventStart, &HookEvent::default()).unwrap();
+    let event = HookEvent::clip("garden", Local::now(), Path::new("/tmp/clip.mp4"));
+    hooks.run(Hook::ClipFinalized, &event).unwrap();
+    hooks.wait();
+
+    let env = fs::read_to_string(&out).unwrap();
+    fs::remove_file(&out).unwrap();
+    assert_eq!(env.trim(), "on_clip_finalized|garden|/tmp/clip.mp4|");
+    assert!(errors.lock().unwrap().is_empty());
+}
+
+#[test]
+fn hook_timeout_and_concurrency() {
+    let (hooks, errors) = hook_runner(HookSettings {
+        on_event_start: "sleep 5".to_string(),
+        on_event_end: "exit 3".to_string(),
+        timeout: 1,
+        max_concurrent: 1,
+        ..Default::default()
+    });
+
+    // The caller never waits for the hook.
+    let start = Instant::now();
+    hooks.run(Hook::EventStart, &HookEvent::default()).unwrap();
+    assert!(start.elapsed().as_secs() < 1);
+    assert_eq!(hooks.running(), 1);
+
+    // Further hooks are skipped while the limit is reached.
+    let e = hooks.run(Hook::EventEnd, &HookEvent::default()).unwrap_err();
+    assert!(e.to_string().contains("on_event_end skipped"));
+
+    // The hanging hook is killed on timeout.
+    hooks.wait();
+    assert!(start.elapsed().as_secs() < 5);
+    hooks.run(Hook::EventEnd, &HookEvent::default()).unwrap();
+    hooks.wait();
+
+    let errors = errors.lock().unwrap();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0], "on_event_start: killed after 1s timeout");
+    assert!(errors[1].starts_with("on_event_end: exit status: 3"));
+}
+
+#[test]
+fn hook_timeout_kills_started_commands() {
+    let directory = test_directory("hook-group");
+    let pid_file = directory.join("pid");
+    let (hooks, errors) = hook_runner(HookSettings {
+        on_event_start: format!("sleep 30 & echo $! > {}; wait", pid_file.display()),
+        timeout: 1,
+        ..Default::default()
+    });
+
+    hooks.run(Hook::EventStart, &HookEvent::default()).unwrap();
+    hooks.wait();
+    assert_eq!(errors.lock().unwrap()[0], "on_event_start: killed after 1s timeout");
+
+    // The background command is killed along with the hook shell (exited or zombie).
+    let pid = fs::read_to_string(&pid_file).unwrap().trim().to_string();
+    let dead = || {
+        fs::read_to_string(format!("/proc/{pid}/stat"))
+            .ok()
+            .is_none_or(|stat| stat.rsplit(')').next().unwrap().trim_start().starts_with('Z'))
+    };
+    let start = Instant::now();
+    while !dead() && start.elapsed() < Duration::from_secs(2) {
+        thread::sleep(Duration::from_millis(50));
+    }
+    assert!(dead());
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn hook_timeout_must_be_positive() {
+    let config = toml::from_str::<Config>("[hooks]\ntimeout = 1").unwrap();
+    assert_eq!(config.hooks.timeout, 1);
+    assert!(toml::from_str::<Config>("[hooks]\ntimeout = 0").is_err());
+}
+
+/// Build a motion event of the given duration in seconds.
+fn motion_event(start: chrono::DateTime<Local>, seconds: i64, peak_score: f64) -> MotionEvent {
+    MotionEvent {
+        id: None,
+        camera: "garden".to_string(),
+        start,
+        end: start + chrono::Duration::seconds(seconds),
+        peak_score,
+        zones: Default::default(),
+        snapshot: None,
+    }
+}
+
+#[test]
+fn notifier_cooldown_and_ended_alerts() {
+    let now = Local::now();
+    let mut notifier = Notifier::new("garden", chrono::Duration::seconds(60), None, now);
+
+    // The first event is alerted: its end waits for the clip.
+    let first = motion_event(now, 12, 0.4);
+    assert!(notifier.started(&first));
+    assert!(notifier.ended(first.clone(), first.end).is_empty());
+    assert!(notifier.next_due(first.end).is_some());
+    let alerts = notifier.clip(Path::new("/clips/first.mp4"), first.end);
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].title, "Motion ended");
+    assert_eq!(alerts[0].body, "duration 12.0s, peak score 0.400, clip first.mp4");
+    assert_eq!(alerts[0].clip.as_deref(), Some(Path::new("/clips/first.mp4")));
+    assert!(notifier.next_due(first.end).is_none());
+
+    // The second event starts within the cooldown: neither its start nor its end are alerted.
+    let second = motion_event(now + chrono::Duration::seconds(30), 5, 0.9);
+    assert!(!notifier.started(&second));
+    assert!(notifier.ended(second.clone(), second.end).is_empty());
+    assert!(notifier.next_due(second.end).is_none());
+
+    // Past the cooldown, the end is alerted without clip once the clip wait is over.
+    let third = motion_event(now + chrono::Duration::seconds(90), 3, 0.2);
+    assert!(notifier.started(&third));
+    assert!(notifier.ended(third.clone(), third.end).is_empty());
+    assert!(notifier.poll(third.end).is_empty());
+    let alerts = notifier.poll(third.end + chrono::Duration::seconds(CLIP_WAIT));
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].body, "duration 3.0s, peak score 0.200");
+    assert!(alerts[0].clip.is_none());
+}
+
+#[test]
+fn notifier_digest() {
+    let now = Local::now();
+    let mut notifier = Notifier::new(
+        "garden",
+        chrono::Duration::seconds(5),
+        Some(chrono::Duration::minutes(10)),
+        now,
+    );
+
+    // No per event alerts in digest mode.
+    for (offset, peak_score) in [(60, 0.3), (120, 0.7)] {
+        let event = motion_event(now + chrono::Duration::seconds(offset), 4, peak_score);
+        assert!(!notifier.started(&event));
+        assert!(notifier.ended(event.clone(), event.end).is_empty());
+        assert!(notifier.clip(Path::new("/clips/clip.mp4"), event.end).is_empty());
+    }
+
+    assert!(notifier.poll(now + chrono::Duration::minutes(9)).is_empty());
+    let alerts = notifier.poll(now + chrono::Duration::minutes(10));
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].title, "Motion digest");
+    let lines: Vec<&str> = alerts[0].body.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("2 motion events since"));
+    assert!(lines[0].ends_with("peak score 0.700"));
+    assert!(lines[2].ends_with("4.0s score 0.700, clip clip.mp4"));
+
+    // Empty digests are not sent.
+    assert!(notifier.poll(now + chrono::Duration::minutes(20)).is_empty());
+    assert!(notifier.flush(now + chrono::Duration::minutes(25)).is_empty());
+}
+
+/// Parse the `[schedule]` table of a configuration file.
+fn schedule_settings(toml: &str) -> ScheduleSettings {
+    toml::from_str::<Config>(toml).unwrap().schedule
+}
+
+/// Return the UTC date&time as local date&time.
+fn utc(day: u32, hour: u32, min: u32) -> chrono::DateTime<Local> {
+    use chrono::TimeZone;
+    // 2025-01-06 is a Monday.
+    chrono::Utc
+        .with_ymd_and_hms(2025, 1, 6 + day, hour, min, 0)
+        .unwrap()
+        .with_timezone(&Local)
+}
+
+#[test]
+fn schedule_time_ranges() {
+    let settings = schedule_settings(
+        r#"
+        [schedule]
+        timezone = "UTC"
+
+        [[schedule.alerts]]
+        days = ["mon", "tue", "wed", "thu", "fri"]
+        start = "18:00"
+        end = "08:00"
+
+        [[schedule.alerts]]
+        days = ["saturday", "Sun"]
+
+        [[schedule.recording]]
+        start = "06:00"
+        end = "22:00"
+        cameras = ["garden"]
+        "#,
+    );
+    let directory = std::env::temp_dir().join("rustymode-no-arming");
+    let mut arming = Arming::new(&settings, "garden", &directory).unwrap();
+
+    // Weekday nights, until Saturday morning.
+    assert!(!arming.alerts_armed(utc(0, 12, 0)));
+    assert!(arming.alerts_armed(utc(0, 18, 0)));
+    assert!(arming.alerts_armed(utc(1, 7, 59)));
+    assert!(!arming.alerts_armed(utc(1, 8, 0)));
+    assert!(arming.alerts_armed(utc(4, 23, 0)));
+    assert!(!arming.alerts_armed(utc(0, 7, 0)));
+    // Whole weekend days, until the Monday night.
+    assert!(arming.alerts_armed(utc(6, 12, 0)));
+    assert!(!arming.alerts_armed(utc(7, 12, 0)));
+
+    assert!(arming.recording(utc(0, 6, 0)));
+    assert!(!arming.recording(utc(0, 22, 30)));
+
+    // Ranges of other cameras don't apply: always recording.
+    let arming = Arming::new(&settings, "door", &directory).unwrap();
+    assert!(arming.recording(utc(0, 22, 30)));
+
+    // Invalid schedules are rejected.
+    assert!(Arming::new(
+        &schedule_settings("[schedule]\ntimezone = \"Mars/Olympus\""),
+        "garden",
+        &directory
+    )
+    .is_err());
+    assert!(toml::from_str::<Config>("[[schedule.alerts]]\nstart = \"25:00\"").is_err());
+    assert!(toml::from_str::<Config>("[[schedule.alerts]]\ndays = [\"someday\"]").is_err());
+}
+
+#[test]
+fn schedule_manual_override() {
+    let directory = std::env::temp_dir().join(format!("rustymode-arming-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let settings = schedule_settings(
+        r#"
+        [schedule]
+        timezone = "UTC"
+
+        [[schedule.alerts]]
+        days = []
+        start = "00:00"
+        end = "00:01"
+        "#,
+    );
+
+    // The manual state persists in the state file, per camera.
+    schedule::set_state(&directory, "garden", ArmState::Disarmed).unwrap();
+    schedule::set_state(&directory, "door", ArmState::Armed).unwrap();
+    let mut garden = Arming::new(&settings, "garden", &directory).unwrap();
+    let mut door = Arming::new(&settings, "door", &directory).unwrap();
+    assert_eq!(garden.state(), ArmState::Disarmed);
+    assert!(!garden.alerts_armed(utc(0, 0, 0)));
+    assert!(door.alerts_armed(utc(0, 12, 0)));
+
+    // Back to the schedule.
+    schedule::set_state(&directory, "garden", ArmState::Auto).unwrap();
+    let states = schedule::read_states(&directory).unwrap();
+    assert!(!states.contains_key("garden"));
+    assert_eq!(states["door"].state, ArmState::Armed);
+    let mut garden = Arming::new(&settings, "garden", &directory).unwrap();
+    assert_eq!(garden.state(), ArmState::Auto);
+    assert!(!garden.alerts_armed(utc(0, 12, 0)));
+
+    fs::remove_dir_all(&directory).unwrap();
+}
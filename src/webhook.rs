@@ -0,0 +1,223 @@
+// rustymode: Fork of bombuscv, originally an OpenCV-based motion detection/recording software built for research on bumblebees.
+// Originally developed as bombuscv by Marco Radocchia (C) 2022
+// Modified and renamed to rustymode by Dmitry Sobolev (C) 2025
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use crate::{
+    alert::{Alert, Severity},
+    error::ErrorKind,
+    BoundingBox, Messenger,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{collections::BTreeMap, thread, time::Duration};
+use url::Url;
+
+/// Generic HTTP webhook settings.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct WebhookSettings {
+    /// URL the events are POSTed to (required: remove the table to disable the webhook).
+    pub url: String,
+
+    /// Additional HTTP headers sent with each request.
+    pub headers: BTreeMap<String, String>,
+
+    /// HMAC-SHA256 signing secret (empty to disable signing).
+    pub secret: String,
+
+    /// Header carrying the `sha256=<hex>` request body signature.
+    pub signature_header: String,
+
+    /// Request timeout in seconds.
+    pub timeout: u16,
+
+    /// Number of retries after a failed request.
+    pub retries: u8,
+
+    /// Delay in milliseconds before the first retry, doubled on each further retry.
+    pub retry_delay: u64,
+
+    /// Embed the base64 encoded JPEG snapshot in the document.
+    pub snapshot: bool,
+}
+
+/// Implement the Default trait for WebhookSettings.
+impl Default for WebhookSettings {
+    /// Default webhook settings.
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            headers: BTreeMap::new(),
+            secret: String::new(),
+            signature_header: String::from("X-Rustymode-Signature"),
+            timeout: 10,
+            retries: 3,
+            retry_delay: 500,
+            snapshot: true,
+        }
+    }
+}
+
+/// JSON document POSTed to the webhook for each alert.
+///
+/// # Fields
+/// * time: alert date&time (RFC 3339)
+/// * camera: camera name
+/// * title: alert title
+/// * body: alert details
+/// * severity: alert severity
+/// * event_id: motion event id in the event database
+/// * score: motion score (0-1), for motion alerts
+/// * boxes: motion bounding boxes, in frame coordinates
+/// * snapshot: base64 encoded JPEG snapshot
+#[derive(Serialize, Debug, Clone)]
+pub struct WebhookEvent {
+    pub time: String,
+    pub camera: String,
+    pub title: String,
+    pub body: String,
+    pub severity: Severity,
+    pub event_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+    pub boxes: Vec<BoundingBox>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<String>,
+}
+
+impl WebhookEvent {
+    /// Create the webhook document of an alert, embedding its snapshot if requested.
+    pub fn new(alert: &Alert, with_snapshot: bool) -> Self {
+        Self {
+            time: alert.timestamp.to_rfc3339(),
+            camera: alert.camera.clone(),
+            title: alert.title.clone(),
+            body: alert.body.clone(),
+            severity: alert.severity,
+            event_id: alert.event_id,
+            score: alert.detection.as_ref().map(|detection| detection.score),
+            boxes: alert
+                .detection
+                .as_ref()
+                .map(|detection| detection.boxes.clone())
+                .unwrap_or_default(),
+            snapshot: alert
+                .snapshot()
+                .filter(|_| with_snapshot)
+                .map(|snapshot| STANDARD.encode(&snapshot.data)),
+        }
+    }
+}
+
+/// Return the `sha256=<hex>` HMAC-SHA256 signature of the body.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key size");
+    mac.update(body);
+
+    let signature: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    format!("sha256={signature}")
+}
+
+/// Generic HTTP webhook messenger: POSTs a JSON document for each alert.
+///
+/// # Fields
+/// * settings: webhook settings
+/// * agent: HTTP client
+pub struct WebhookMessenger {
+    settings: WebhookSettings,
+    agent: ureq::Agent,
+}
+
+/// Create the webhook messenger described by the settings.
+pub fn from_settings(settings: &WebhookSettings) -> Result<WebhookMessenger, ErrorKind> {
+    if settings.url.is_empty() {
+        return Err(ErrorKind::WebhookErr("missing webhook URL".to_string()));
+    }
+    Url::parse(&settings.url)
+        .map_err(|e| ErrorKind::WebhookErr(format!("invalid webhook URL: {e}")))?;
+
+    Ok(WebhookMessenger {
+        settings: settings.clone(),
+        agent: ureq::AgentBuilder::new()
+            .timeout(Duration::from_secs(settings.timeout.into()))
+            .build(),
+    })
+}
+
+impl WebhookMessenger {
+    /// POST the event to the webhook, retrying on network errors and 5xx/429 responses.
+    pub fn post_event(&self, event: &WebhookEvent) -> Result<(), ErrorKind> {
+        let body = serde_json::to_vec(event).map_err(|e| ErrorKind::WebhookErr(e.to_string()))?;
+
+        let mut delay = Duration::from_millis(self.settings.retry_delay);
+        let mut attempt = 0;
+        loop {
+            let error = match self.post(&body) {
+                Ok(()) => return Ok(()),
+                Err(e) => e,
+            };
+
+            let retryable = match &error {
+                ureq::Error::Status(code, _) => *code == 429 || *code >= 500,
+                ureq::Error::Transport(_) => true,
+            };
+            if !retryable || attempt >= self.settings.retries {
+                return Err(ErrorKind::WebhookErr(error.to_string()));
+            }
+
+            thread::sleep(delay);
+            delay *= 2;
+            attempt += 1;
+        }
+    }
+
+    /// POST the JSON body once, signed if a secret is configured.
+    fn post(&self, body: &[u8]) -> Result<(), ureq::Error> {
+        let mut request = self
+            .agent
+            .post(&self.settings.url)
+            .set("Content-Type", "application/json");
+        for (name, value) in &self.settings.headers {
+            request = request.set(name, value);
+        }
+        if !self.settings.secret.is_empty() {
+            request = request.set(
+                &self.settings.signature_header,
+                &sign(&self.settings.secret, body),
+            );
+        }
+
+        request.send_bytes(body).map(|_| ())
+    }
+}
+
+impl Messenger for WebhookMessenger {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    fn send(&mut self, alert: &Alert) -> Result<(), ErrorKind> {
+        self.post_event(&WebhookEvent::new(alert, self.settings.snapshot))
+    }
+}
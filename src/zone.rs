@@ -0,0 +1,204 @@
+// rustymode: Fork of bombuscv, originally an OpenCV-based motion detection/recording software built for research on bumblebees.
+// Originally developed as bombuscv by Marco Radocchia (C) 2022
+// Modified and renamed to rustymode by Dmitry Sobolev (C) 2025
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use crate::error::ErrorKind;
+use opencv::{
+    core::{Point, Scalar, Size, Vector, CV_8UC1},
+    imgcodecs::imwrite,
+    imgproc::{fill_poly, polylines, LineTypes},
+    prelude::{Mat, MatTraitConst},
+};
+use serde::{de, Deserialize, Deserializer};
+use std::path::Path;
+
+/// Custom deserializer for `points` field.
+/// Polygons need at least 3 vertices, expressed in normalized `[0, 1]` coordinates.
+fn deserialize_points<'de, D>(points: D) -> Result<Vec<[f64; 2]>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let points = Vec::<[f64; 2]>::deserialize(points)?;
+    if points.len() < 3 {
+        return Err(de::Error::custom("zone polygon must have at least 3 points"));
+    }
+    if points
+        .iter()
+        .flatten()
+        .any(|coord| !(0. ..=1.).contains(coord))
+    {
+        return Err(de::Error::custom(
+            "zone coordinates must be normalized within 0 and 1",
+        ));
+    }
+
+    Ok(points)
+}
+
+/// Type of motion detection zone.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ZoneKind {
+    /// Motion is detected only inside include zones.
+    Include,
+    /// Motion is never detected inside exclude zones.
+    Exclude,
+}
+
+/// Motion detection zone.
+///
+/// # Fields
+/// * name: zone name
+/// * kind: include or exclude zone
+/// * points: polygon vertices as `[x, y]` pairs, normalized to the frame size
+#[derive(Deserialize, Debug, Clone)]
+pub struct Zone {
+    #[serde(default)]
+    pub name: String,
+    pub kind: ZoneKind,
+    #[serde(deserialize_with = "deserialize_points")]
+    pub points: Vec<[f64; 2]>,
+}
+
+impl Zone {
+    /// Return zone polygon scaled to the given frame size.
+    fn polygon(&self, size: Size) -> Vector<Point> {
+        self.points
+            .iter()
+            .map(|[x, y]| {
+                Point::new(
+                    (x * f64::from(size.width - 1)).round() as i32,
+                    (y * f64::from(size.height - 1)).round() as i32,
+                )
+            })
+            .collect()
+    }
+}
+
+/// Return zone polygons of the given kind, scaled to the given frame size.
+fn polygons(zones: &[Zone], kind: ZoneKind, size: Size) -> Vector<Vector<Point>> {
+    zones
+        .iter()
+        .filter(|zone| zone.kind == kind)
+        .map(|zone| zone.polygon(size))
+        .collect()
+}
+
+/// Build the single channel motion detection mask for the given frame size: active pixels are
+/// set to 255, masked out pixels to 0.
+///
+/// If include zones are defined only their area is active, otherwise the whole frame is;
+/// exclude zones are then removed from the active area. Returns `None` if no zones are defined.
+pub fn build_mask(zones: &[Zone], size: Size) -> Option<Mat> {
+    if zones.is_empty() {
+        return None;
+    }
+
+    let include = polygons(zones, ZoneKind::Include, size);
+    let exclude = polygons(zones, ZoneKind::Exclude, size);
+
+    // No include zones means the whole frame is active.
+    let background = if include.is_empty() { 255. } else { 0. };
+    let mut mask =
+        Mat::new_rows_cols_with_default(size.height, size.width, CV_8UC1, Scalar::all(background))
+            .expect("unable to allocate zone mask");
+
+    for (shapes, value) in [(include, 255.), (exclude, 0.)] {
+        if !shapes.is_empty() {
+            fill_poly(
+                &mut mask,
+                &shapes,
+                Scalar::all(value),
+                LineTypes::LINE_8 as i32,
+                0,
+                Point::new(0, 0),
+            )
+            .expect("fill_poly failed");
+        }
+    }
+
+    Some(mask)
+}
+
+/// Build a single channel mask for each named include zone of the given frame size, to tell
+/// which zones the motion touched.
+pub fn build_zone_masks(zones: &[Zone], size: Size) -> Vec<(String, Mat)> {
+    zones
+        .iter()
+        .filter(|zone| zone.kind == ZoneKind::Include && !zone.name.is_empty())
+        .map(|zone| {
+            let mut mask =
+                Mat::new_rows_cols_with_default(size.height, size.width, CV_8UC1, Scalar::all(0.))
+                    .expect("unable to allocate zone mask");
+            let shapes: Vector<Vector<Point>> = Vector::from_iter([zone.polygon(size)]);
+            fill_poly(
+                &mut mask,
+                &shapes,
+                Scalar::all(255.),
+                LineTypes::LINE_8 as i32,
+                0,
+                Point::new(0, 0),
+            )
+            .expect("fill_poly failed");
+            (zone.name.clone(), mask)
+        })
+        .collect()
+}
+
+/// Write a PNG preview of the zones over the given frame: masked out areas are dimmed and zone
+/// borders are drawn (include zones in green, exclude zones in red).
+pub fn write_preview(zones: &[Zone], frame: &Mat, path: &Path) -> Result<(), ErrorKind> {
+    let size = frame.size().map_err(|e| ErrorKind::MaskPreviewErr(e.to_string()))?;
+
+    // Dim the whole frame, then copy back the original pixels of the active area.
+    let mut preview = Mat::default();
+    frame
+        .convert_to(&mut preview, -1, 0.4, 0.)
+        .map_err(|e| ErrorKind::MaskPreviewErr(e.to_string()))?;
+    match build_mask(zones, size) {
+        Some(mask) => frame.copy_to_masked(&mut preview, &mask),
+        None => frame.copy_to(&mut preview),
+    }
+    .map_err(|e| ErrorKind::MaskPreviewErr(e.to_string()))?;
+
+    for (kind, color) in [
+        (ZoneKind::Include, Scalar::new(0., 255., 0., 0.)),
+        (ZoneKind::Exclude, Scalar::new(0., 0., 255., 0.)),
+    ] {
+        let shapes = polygons(zones, kind, size);
+        if !shapes.is_empty() {
+            polylines(
+                &mut preview,
+                &shapes,
+                true, // Closed polylines.
+                color,
+                2, // Thickness.
+                LineTypes::LINE_8 as i32,
+                0,
+            )
+            .map_err(|e| ErrorKind::MaskPreviewErr(e.to_string()))?;
+        }
+    }
+
+    let path = path.to_str().expect("invalid UTF-8 preview path");
+    match imwrite(path, &preview, &Vector::new()) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(ErrorKind::MaskPreviewErr(format!(
+            "unable to write mask preview to {path}"
+        ))),
+        Err(e) => Err(ErrorKind::MaskPreviewErr(e.to_string())),
+    }
+}